@@ -20,12 +20,14 @@ pub struct Opts {
     pub cmd: SubCommand,
 }
 
+/// Parsed once per run, so the size difference between variants does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats")]
     Csv(CsvOpts),
-    #[command(name = "genpass", about = "Generate a random password")]
+    #[command(name = "genpass", about = "Generate random passwords, or check existing ones")]
     GenPass(GenPassOpts),
     #[command(subcommand, about = "Base64 encode/decode")]
     Base64(Base64SubCommand),
@@ -1,58 +1,416 @@
+mod cat;
+mod clean;
+mod diff;
+mod import;
+mod join;
+mod query;
+mod render;
+mod sample;
+mod show;
+mod shuffle;
+mod split;
+mod stats;
+mod validate;
+
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use clap::Parser;
+pub use cat::*;
+use clap::{Args, Parser, Subcommand};
+pub use clean::*;
+pub use diff::*;
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
+pub use import::*;
+pub use join::*;
+pub use query::*;
+pub use render::*;
+pub use sample::*;
+use serde::{Deserialize, Serialize};
+pub use show::*;
+pub use shuffle::*;
+pub use split::*;
+pub use stats::*;
+pub use validate::*;
 
 use super::verify_file_exists;
-use crate::CmdExecutor;
+use crate::{CmdExecutor, Rejects};
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    /// Newline delimited json, one record per line
+    Ndjson,
+    /// An array of tables named `rows`
+    Toml,
+    /// A GitHub flavored markdown table
+    Markdown,
+    /// A standalone html page holding a table
+    Html,
+    /// An Excel workbook with a single sheet
+    Xlsx,
+    /// Apache Parquet with typed columns
+    Parquet,
+    /// An Arrow IPC file (feather v2)
+    Arrow,
+}
+
+/// Compression codec of parquet column chunks
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ColumnarCompression {
+    None,
+    #[default]
+    Snappy,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CsvTrim {
+    #[default]
+    None,
+    Headers,
+    Fields,
+    All,
+}
+
+/// Value type of a CSV column when converting to typed formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvColumnType {
+    Int,
+    Float,
+    Bool,
+    Date,
+    String,
+}
+
+/// A `column=type` pair given by `--type`
+#[derive(Debug, Clone)]
+pub struct CsvTypeOverride {
+    pub column: String,
+    pub ty: CsvColumnType,
 }
 
+/// `rcli csv -i input.csv` converts, the subcommands offer the other operations
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
-    #[arg(short, long, help="Input CSV file", value_name="FILE", value_parser = verify_file_exists)]
-    pub input: PathBuf,
-    #[arg(short, long, help = "Output JSON file", value_name = "FILE")] // "output.json".into()
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    /// required unless a subcommand is given, so it is only `None` for the subcommands
+    #[arg(short, long, help="Input CSV file, `-` for stdin", value_name="FILE", value_parser = verify_file_exists, required = true)]
+    pub input: Option<PathBuf>,
+    #[arg(short, long, help = "Output file, `-` for stdout", value_name = "FILE")]
+    // "output.json".into()
     pub output: Option<PathBuf>,
-    #[arg(long, help = "Supported Output Format", value_parser = parse_format, default_value = "json")]
+    #[arg(long, help = "Output format: json, yaml, ndjson, toml, md, html, xlsx, parquet or arrow", value_parser = parse_format, default_value = "json")]
     /// When we need a immediate `default_value_t`, we must implement `Copy` and `ToString` trait for the type
     pub format: OutputFormat,
-    /// short option names must be unique for each argument, --help and --header conflict
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+    #[arg(long, help = "Build nested objects and arrays from `a.b` and `a[0]` column names")]
+    pub unflatten: bool,
+    #[command(flatten)]
+    pub filter: CsvFilterOpts,
+    #[command(flatten)]
+    pub columnar: CsvColumnarOpts,
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(about = "Convert JSON, NDJSON or YAML records back to CSV")]
+    Import(CsvImportOpts),
+    #[command(about = "Show CSV as an aligned table")]
+    Show(CsvShowOpts),
+    #[command(about = "Profile every column: type, nulls, distinct values, ranges and more")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Validate CSV against a yaml schema, failing on any violation")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Compare two CSV files by key: added, removed and changed rows")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Run a SQL query over one or more CSV files")]
+    Query(CsvQueryOpts),
+    #[command(about = "Join two CSV files on key columns (inner, left or outer)")]
+    Join(CsvJoinOpts),
+    #[command(about = "Concatenate CSV files, aligning their headers")]
+    Cat(CsvCatOpts),
+    #[command(about = "Split a CSV file by row count or column value")]
+    Split(CsvSplitOpts),
+    #[command(about = "Trim, normalize, deduplicate and fix dates, logging every change to stderr")]
+    Clean(CsvCleanOpts),
+    #[command(about = "Take a random sample of rows, optionally per group")]
+    Sample(CsvSampleOpts),
+    #[command(about = "Write the rows in random order")]
+    Shuffle(CsvShuffleOpts),
+    #[command(about = "Render every row, or the whole table, through a jinja template")]
+    Render(CsvRenderOpts),
+}
+
+/// Options describing how the input CSV is laid out, shared by every csv command
+#[derive(Debug, Clone, Args)]
+pub struct CsvReaderOpts {
+    /// the first row is a header row unless `--no-header` is given, the last of the two flags wins
+    #[arg(long, help = "The first row is a header row (default)", overrides_with = "no_header")]
+    pub header: bool,
     #[arg(
         long,
-        help = "Indicates that the CSV file has a header row
-",
-        default_value_t = false
+        help = "The input has no header row, columns are named col0, col1, ... or by --columns",
+        overrides_with = "header"
     )]
-    pub header: bool,
-    #[arg(short, long, help = "Delimiter character", default_value_t = ',')]
-    pub delimiter: char,
+    pub no_header: bool,
+    #[arg(
+        long,
+        help = "Column names to use instead of the header row",
+        value_name = "NAMES",
+        value_delimiter = ','
+    )]
+    pub columns: Option<Vec<String>>,
+    #[arg(short, long, help = "Delimiter character, `\\t` or `tab` for tabs", default_value = ",", value_parser = parse_ascii_char)]
+    pub delimiter: u8,
+    #[arg(long, help = "Quote character", default_value = "\"", value_parser = parse_ascii_char)]
+    pub quote: u8,
+    #[arg(long, help = "Escape character inside quotes, disables doubled quotes", value_parser = parse_ascii_char)]
+    pub escape: Option<u8>,
+    #[arg(long, help = "Skip lines starting with this character", value_parser = parse_ascii_char)]
+    pub comment: Option<u8>,
+    #[arg(long, help = "Trim whitespace: none, headers, fields or all", default_value = "none", value_parser = parse_trim)]
+    pub trim: CsvTrim,
+    /// `None` sniffs a BOM, then guesses from the first bytes
+    #[arg(long, help = "Input encoding, e.g. gbk, shift_jis, windows-1252 or utf-16le; detected when omitted", value_name = "LABEL", value_parser = parse_encoding)]
+    pub encoding: Option<&'static Encoding>,
+    #[arg(
+        long,
+        help = "Sheet of an xlsx/xls/ods input, by name or 1-based position (default: the first)",
+        value_name = "SHEET"
+    )]
+    pub sheet: Option<String>,
+    #[arg(
+        long,
+        help = "Skip rows with a wrong field count or invalid UTF-8 instead of failing, see --rejects"
+    )]
+    pub skip_bad_rows: bool,
+    #[arg(
+        long,
+        help = "Where --skip-bad-rows writes rejected rows (default: <input stem>.rejects.csv)",
+        value_name = "FILE",
+        requires = "skip_bad_rows"
+    )]
+    pub rejects: Option<PathBuf>,
+}
+
+/// Row filtering, sorting and column projection applied before any output
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvFilterOpts {
+    #[arg(
+        long,
+        help = "Only keep these columns, in this order",
+        value_name = "COLUMNS",
+        value_delimiter = ','
+    )]
+    pub select: Option<Vec<String>>,
+    /// `where` is a keyword, so the field has another name
+    #[arg(
+        long = "where",
+        help = "Only keep rows matching the expression, e.g. \"Kit Number > 10 && Nationality == Italy\"",
+        value_name = "EXPR"
+    )]
+    pub filter: Vec<String>,
+    #[arg(
+        long,
+        help = "Sort rows by this column (numbers and dates compare by value)",
+        value_name = "COLUMN"
+    )]
+    pub sort: Option<String>,
+    #[arg(long, help = "Sort in descending order", requires = "sort")]
+    pub desc: bool,
+    #[arg(long, help = "Keep at most N rows", value_name = "N")]
+    pub limit: Option<usize>,
+}
+
+/// Options controlling how string fields become typed values
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvTypeOpts {
+    #[arg(
+        long,
+        help = "Infer integers, floats, booleans, ISO dates and empty-as-null per column from the first 1024 rows (default)",
+        overrides_with = "no_infer"
+    )]
+    pub infer: bool,
+    #[arg(long, help = "Keep every field as a string", overrides_with = "infer")]
+    pub no_infer: bool,
+    #[arg(
+        long = "type",
+        help = "Per column type overrides, e.g. \"Kit Number=int,DOB=date\"",
+        value_name = "COLUMN=TYPE",
+        value_delimiter = ',',
+        value_parser = parse_type_override
+    )]
+    pub types: Vec<CsvTypeOverride>,
+}
+
+/// Layout of parquet and arrow output
+#[derive(Debug, Clone, Args)]
+pub struct CsvColumnarOpts {
+    /// also the size of every arrow record batch, a whole group is held in memory before it is written
+    #[arg(long, help = "Rows per parquet row group, buffered in memory until the group is written", value_name = "N", default_value = "65536", value_parser = parse_row_group_size)]
+    pub row_group_size: usize,
+    #[arg(long, help = "Parquet compression: none, snappy or zstd", default_value = "snappy", value_parser = parse_compression)]
+    pub compression: ColumnarCompression,
+}
+
+/// The record options a csv command hands to its process function.
+/// Commands without flags for a part leave it at its default
+#[derive(Debug, Clone, Default)]
+pub struct CsvProcessOpts {
+    pub reader: CsvReaderOpts,
+    pub types: CsvTypeOpts,
+    pub filter: CsvFilterOpts,
+    pub columnar: CsvColumnarOpts,
+}
+
+impl CsvTypeOpts {
+    pub fn should_infer(&self) -> bool {
+        !self.no_infer
+    }
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            header: false,
+            no_header: false,
+            columns: None,
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            trim: CsvTrim::None,
+            encoding: None,
+            sheet: None,
+            skip_bad_rows: false,
+            rejects: None,
+        }
+    }
+}
+
+impl Default for CsvColumnarOpts {
+    fn default() -> Self {
+        Self { row_group_size: 65536, compression: ColumnarCompression::Snappy }
+    }
+}
+
+impl CsvReaderOpts {
+    pub fn has_headers(&self) -> bool {
+        !self.no_header
+    }
+
+    /// Side file for rows rejected by `--skip-bad-rows`, next to the input unless given
+    pub fn rejects_path(&self, input: &Path) -> PathBuf {
+        if let Some(path) = &self.rejects {
+            return path.clone();
+        }
+        match input.to_str() {
+            Some("-") => PathBuf::from("stdin.rejects.csv"),
+            _ => input.with_extension("rejects.csv"),
+        }
+    }
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let Some(input) = self.input else {
+            anyhow::bail!("--input is required");
+        };
         let output = if let Some(output) = self.output {
             output
         } else {
             PathBuf::from(format!("output.{}", self.format))
         };
-        crate::process_csv(&self.input, &output, self.format)
+        let mut rejects = Rejects::default();
+        let opts = CsvProcessOpts {
+            reader: self.reader,
+            types: self.types,
+            filter: self.filter,
+            columnar: self.columnar,
+        };
+        crate::process_csv(&input, &output, self.format, self.unflatten, &opts, &mut rejects)?;
+        report_rejects(rejects)
     }
 }
 
+/// Tell where the rows skipped by `--skip-bad-rows` went, once the command is done
+fn report_rejects(rejects: Rejects) -> anyhow::Result<()> {
+    for (path, count) in rejects.finish()? {
+        eprintln!("Skipped {} bad rows, written to {}", count, path.display());
+    }
+    Ok(())
+}
+
 fn parse_format(format: &str) -> Result<OutputFormat, String> {
     OutputFormat::try_from(format).map_err(|e| e.to_string())
 }
 
+fn parse_compression(compression: &str) -> Result<ColumnarCompression, anyhow::Error> {
+    compression.parse()
+}
+
+fn parse_row_group_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("row group size must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_trim(trim: &str) -> Result<CsvTrim, anyhow::Error> {
+    trim.parse()
+}
+
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("Unknown encoding: {}", label))
+}
+
+fn parse_type_override(value: &str) -> Result<CsvTypeOverride, anyhow::Error> {
+    let Some((column, ty)) = value.rsplit_once('=') else {
+        anyhow::bail!("Type override '{}' must look like COLUMN=TYPE", value);
+    };
+    Ok(CsvTypeOverride { column: column.trim().to_string(), ty: ty.trim().parse()? })
+}
+
+/// the csv crate works on bytes, so delimiter/quote/escape/comment must be a single ASCII char
+fn parse_ascii_char(value: &str) -> Result<u8, String> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok(c as u8),
+                _ => Err(format!("'{}' is not a single ASCII character", value)),
+            }
+        }
+    }
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
         }
     }
 }
@@ -65,7 +423,14 @@ impl TryFrom<&str> for OutputFormat {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value.to_lowercase().as_str() {
             "json" => Ok(OutputFormat::Json),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "toml" => Ok(OutputFormat::Toml),
+            "md" | "markdown" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            "xlsx" | "excel" => Ok(OutputFormat::Xlsx),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(OutputFormat::Arrow),
             _ => anyhow::bail!("Unsupported format: {}", value),
         }
     }
@@ -76,3 +441,135 @@ impl Display for OutputFormat {
         write!(f, "{}", <&str>::from(*self))
     }
 }
+
+impl FromStr for CsvTrim {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(CsvTrim::None),
+            "headers" => Ok(CsvTrim::Headers),
+            "fields" => Ok(CsvTrim::Fields),
+            "all" => Ok(CsvTrim::All),
+            _ => anyhow::bail!("Unsupported trim mode: {}", s),
+        }
+    }
+}
+
+impl Display for CsvTrim {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvTrim::None => write!(f, "none"),
+            CsvTrim::Headers => write!(f, "headers"),
+            CsvTrim::Fields => write!(f, "fields"),
+            CsvTrim::All => write!(f, "all"),
+        }
+    }
+}
+
+impl FromStr for ColumnarCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "uncompressed" => Ok(ColumnarCompression::None),
+            "snappy" => Ok(ColumnarCompression::Snappy),
+            "zstd" => Ok(ColumnarCompression::Zstd),
+            _ => anyhow::bail!("Unsupported compression: {}", s),
+        }
+    }
+}
+
+impl Display for ColumnarCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnarCompression::None => write!(f, "none"),
+            ColumnarCompression::Snappy => write!(f, "snappy"),
+            ColumnarCompression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for CsvColumnType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "int" | "integer" => Ok(CsvColumnType::Int),
+            "float" | "number" => Ok(CsvColumnType::Float),
+            "bool" | "boolean" => Ok(CsvColumnType::Bool),
+            "date" => Ok(CsvColumnType::Date),
+            "string" | "str" => Ok(CsvColumnType::String),
+            _ => anyhow::bail!("Unsupported column type: {}", s),
+        }
+    }
+}
+
+impl Display for CsvColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvColumnType::Int => write!(f, "int"),
+            CsvColumnType::Float => write!(f, "float"),
+            CsvColumnType::Bool => write!(f, "bool"),
+            CsvColumnType::Date => write!(f, "date"),
+            CsvColumnType::String => write!(f, "string"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ascii_char() {
+        assert_eq!(parse_ascii_char(";").unwrap(), b';');
+        assert_eq!(parse_ascii_char("|").unwrap(), b'|');
+        assert_eq!(parse_ascii_char("\\t").unwrap(), b'\t');
+        assert_eq!(parse_ascii_char("tab").unwrap(), b'\t');
+        assert!(parse_ascii_char("ab").is_err());
+        assert!(parse_ascii_char("§").is_err());
+    }
+
+    #[test]
+    fn test_parse_type_override() {
+        let ret = parse_type_override("Kit Number=int").unwrap();
+        assert_eq!(ret.column, "Kit Number");
+        assert_eq!(ret.ty, CsvColumnType::Int);
+        assert!(parse_type_override("DOB").is_err());
+        assert!(parse_type_override("DOB=timestamp").is_err());
+    }
+
+    #[test]
+    fn test_header_flag() -> anyhow::Result<()> {
+        let opts = CsvOpts::try_parse_from(["csv", "-i", "assets/juventus.csv"])?;
+        assert!(opts.reader.has_headers());
+        let opts = CsvOpts::try_parse_from(["csv", "-i", "assets/juventus.csv", "--no-header"])?;
+        assert!(!opts.reader.has_headers());
+        let opts = CsvOpts::try_parse_from(["csv", "-i", "assets/juventus.csv", "--header"])?;
+        assert!(opts.reader.has_headers());
+        let opts = CsvOpts::try_parse_from([
+            "csv",
+            "-i",
+            "assets/juventus.csv",
+            "--no-header",
+            "--header",
+        ])?;
+        assert!(opts.reader.has_headers());
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_required() -> anyhow::Result<()> {
+        assert!(CsvOpts::try_parse_from(["csv"]).is_err());
+        let opts = CsvOpts::try_parse_from(["csv", "-i", "-"])?;
+        assert_eq!(opts.input, Some(PathBuf::from("-")));
+        let opts = CsvOpts::try_parse_from(["csv", "import"])?;
+        assert!(opts.input.is_none());
+        let Some(CsvSubCommand::Import(import)) = opts.cmd else {
+            anyhow::bail!("expected the import subcommand");
+        };
+        assert_eq!(import.input, PathBuf::from("-"));
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    #[arg(help = "CSV files to concatenate, in order", value_parser = verify_file_exists, required = true)]
+    pub inputs: Vec<PathBuf>,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(long, help = "Placeholder for columns missing from a file", default_value = "")]
+    pub missing: String,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        crate::process_csv_cat(
+            &self.inputs,
+            &self.output,
+            &self.missing,
+            &self.reader,
+            &mut rejects,
+        )?;
+        report_rejects(rejects)
+    }
+}
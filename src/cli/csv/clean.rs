@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser};
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Parser)]
+pub struct CsvCleanOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub rules: CsvCleanRules,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+/// What `csv clean` changes. Trimming, NFC normalization and dropping empty rows
+/// are safe for almost any file so they are on by default, the rest is opt-in
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvCleanRules {
+    #[arg(long, help = "Keep leading and trailing whitespace of values")]
+    pub keep_whitespace: bool,
+    #[arg(long, help = "Skip Unicode NFC normalization")]
+    pub no_nfc: bool,
+    #[arg(long, help = "Keep rows whose values are all empty")]
+    pub keep_empty: bool,
+    #[arg(long, help = "Lowercase these columns", value_name = "COLUMNS", value_delimiter = ',')]
+    pub lower: Vec<String>,
+    #[arg(long, help = "Titlecase these columns", value_name = "COLUMNS", value_delimiter = ',')]
+    pub title: Vec<String>,
+    #[arg(
+        long,
+        help = "Rewrite dates in these columns as ISO 8601, e.g. \"Jan 19, 1978\" to 1978-01-19",
+        value_name = "COLUMNS",
+        value_delimiter = ','
+    )]
+    pub dates: Vec<String>,
+    #[arg(long, help = "Drop rows identical to an earlier row")]
+    pub dedup: bool,
+    #[arg(
+        long,
+        help = "Drop rows whose key columns equal those of an earlier row",
+        value_name = "COLUMNS",
+        value_delimiter = ',',
+        conflicts_with = "dedup"
+    )]
+    pub dedup_by: Option<Vec<String>>,
+}
+
+impl CmdExecutor for CsvCleanOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        // changes are logged to stderr so stdout only carries the cleaned csv
+        let mut rejects = Rejects::default();
+        crate::process_csv_clean(
+            &self.input,
+            &self.output,
+            &self.rules,
+            &self.reader,
+            &mut rejects,
+            &mut std::io::stderr().lock(),
+        )?;
+        report_rejects(rejects)
+    }
+}
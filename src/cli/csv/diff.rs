@@ -0,0 +1,89 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Clone, Copy)]
+pub enum DiffFormat {
+    /// `+`/`-`/`~` lines, colored on terminals
+    Text,
+    Json,
+    /// A csv patch with an `_op` column
+    Csv,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(help = "The old CSV file", value_parser = verify_file_exists)]
+    pub old: PathBuf,
+    #[arg(help = "The new CSV file", value_parser = verify_file_exists)]
+    pub new: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Column(s) identifying a row, comma separated for a composite key",
+        value_delimiter = ',',
+        required = true
+    )]
+    pub key: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(long, help = "Output format: text, json or csv", default_value = "text", value_parser = parse_diff_format)]
+    pub format: DiffFormat,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        crate::process_csv_diff(
+            &self.old,
+            &self.new,
+            &self.output,
+            &self.key,
+            &self.reader,
+            &mut rejects,
+            self.format,
+        )?;
+        report_rejects(rejects)
+    }
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            "csv" => Ok(DiffFormat::Csv),
+            _ => anyhow::bail!("Unsupported diff format: {}", s),
+        }
+    }
+}
+
+impl Display for DiffFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffFormat::Text => write!(f, "text"),
+            DiffFormat::Json => write!(f, "json"),
+            DiffFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
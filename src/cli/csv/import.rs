@@ -0,0 +1,84 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::Parser;
+
+use super::parse_ascii_char;
+use crate::CmdExecutor;
+use crate::cli::verify_file_exists;
+
+#[derive(Debug, Clone, Copy)]
+pub enum InputFormat {
+    /// A json array of objects
+    Json,
+    /// One json object per line
+    Ndjson,
+    /// A yaml sequence of mappings
+    Yaml,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvImportOpts {
+    #[arg(short, long, help = "Input file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output CSV file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    /// guessed from the input file extension when omitted, json for stdin
+    #[arg(long, help = "Input format: json, ndjson or yaml", value_parser = parse_input_format)]
+    pub from: Option<InputFormat>,
+    #[arg(long, help = "Placeholder written for keys missing from a record", default_value = "")]
+    pub missing: String,
+    #[arg(short, long, help = "Delimiter character of the output", default_value = ",", value_parser = parse_ascii_char)]
+    pub delimiter: u8,
+}
+
+impl CmdExecutor for CsvImportOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let from = match self.from {
+            Some(from) => from,
+            None => InputFormat::guess(&self.input),
+        };
+        crate::process_csv_import(&self.input, &self.output, from, &self.missing, self.delimiter)
+    }
+}
+
+impl InputFormat {
+    pub fn guess(input: &Path) -> Self {
+        let ext = input.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        ext.parse().unwrap_or(InputFormat::Json)
+    }
+}
+
+fn parse_input_format(format: &str) -> Result<InputFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(InputFormat::Json),
+            "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+            "yaml" | "yml" => Ok(InputFormat::Yaml),
+            _ => anyhow::bail!("Unsupported input format: {}", s),
+        }
+    }
+}
+
+impl Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFormat::Json => write!(f, "json"),
+            InputFormat::Ndjson => write!(f, "ndjson"),
+            InputFormat::Yaml => write!(f, "yaml"),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinHow {
+    /// Only rows with a match on both sides
+    Inner,
+    /// Every left row, right cells empty when unmatched
+    Left,
+    /// Every row of both sides
+    Outer,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(help = "The left CSV file, `-` for stdin", value_parser = verify_file_exists)]
+    pub left: PathBuf,
+    #[arg(help = "The right CSV file, held in memory", value_parser = verify_file_exists)]
+    pub right: PathBuf,
+    #[arg(
+        long,
+        help = "Column(s) to join on, comma separated",
+        value_delimiter = ',',
+        required = true
+    )]
+    pub on: Vec<String>,
+    #[arg(long, help = "Join type: inner, left or outer", default_value = "inner", value_parser = parse_join_how)]
+    pub how: JoinHow,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        crate::process_csv_join(
+            &self.left,
+            &self.right,
+            &self.output,
+            &self.on,
+            self.how,
+            &self.reader,
+            &mut rejects,
+        )?;
+        report_rejects(rejects)
+    }
+}
+
+fn parse_join_how(how: &str) -> Result<JoinHow, anyhow::Error> {
+    how.parse()
+}
+
+impl FromStr for JoinHow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(JoinHow::Inner),
+            "left" => Ok(JoinHow::Left),
+            "outer" | "full" => Ok(JoinHow::Outer),
+            _ => anyhow::bail!("Unsupported join type: {}", s),
+        }
+    }
+}
+
+impl Display for JoinHow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinHow::Inner => write!(f, "inner"),
+            JoinHow::Left => write!(f, "left"),
+            JoinHow::Outer => write!(f, "outer"),
+        }
+    }
+}
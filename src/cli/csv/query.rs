@@ -0,0 +1,111 @@
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use super::{
+    CsvColumnarOpts, CsvProcessOpts, CsvReaderOpts, OutputFormat, parse_format, report_rejects,
+};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects, TableStyle};
+
+/// A csv file registered as a sql table, given as `name=path` or just `path`
+#[derive(Debug, Clone)]
+pub struct CsvQueryTable {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    #[arg(help = "SQL query (sqlite dialect), every input is a table")]
+    pub query: String,
+    #[arg(
+        short,
+        long = "input",
+        help = "Input CSV file as NAME=FILE, or FILE to name the table after the file stem",
+        value_name = "[NAME=]FILE",
+        required = true,
+        value_parser = parse_query_table
+    )]
+    pub inputs: Vec<CsvQueryTable>,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(long, help = "Output format: json, yaml, ndjson, toml, md, html, xlsx, parquet or arrow, a table when omitted", value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub columnar: CsvColumnarOpts,
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let to_terminal = self.output.to_str() == Some("-") && std::io::stdout().is_terminal();
+        let style = if to_terminal { TableStyle::Boxed } else { TableStyle::Plain };
+        let opts =
+            CsvProcessOpts { reader: self.reader, columnar: self.columnar, ..Default::default() };
+        let mut rejects = Rejects::default();
+        crate::process_csv_query(
+            &self.query,
+            &self.inputs,
+            &self.output,
+            &opts,
+            &mut rejects,
+            self.format,
+            style,
+        )?;
+        report_rejects(rejects)
+    }
+}
+
+fn parse_query_table(value: &str) -> Result<CsvQueryTable, String> {
+    if let Some((name, path)) = value.split_once('=') {
+        if !name.is_empty() && !Path::new(value).exists() {
+            let path = verify_file_exists(path)?;
+            return Ok(CsvQueryTable { name: name.to_string(), path });
+        }
+    }
+    let path = verify_file_exists(value)?;
+    Ok(CsvQueryTable { name: table_name(&path), path })
+}
+
+/// The file stem with anything but letters, digits and `_` replaced, so it needs no quoting.
+/// A stem that does not start with a letter or `_` gets a `_` in front, e.g. `_2024` for `2024.csv`
+fn table_name(path: &Path) -> String {
+    if path.to_str() == Some("-") {
+        return "stdin".to_string();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = stem
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() || c == '_' => name,
+        _ => format!("_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_table() {
+        let table = parse_query_table("players=assets/juventus.csv").unwrap();
+        assert_eq!(table.name, "players");
+        assert_eq!(table.path, PathBuf::from("assets/juventus.csv"));
+        assert_eq!(parse_query_table("assets/juventus.csv").unwrap().name, "juventus");
+        assert_eq!(parse_query_table("-").unwrap().name, "stdin");
+        assert_eq!(table_name(Path::new("data/roster-2024.v2.csv")), "roster_2024_v2");
+        assert_eq!(table_name(Path::new("data/2024.csv")), "_2024");
+        assert!(parse_query_table("players=missing.csv").is_err());
+    }
+}
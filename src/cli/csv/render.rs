@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, CsvTypeOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Parser)]
+pub struct CsvRenderOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(short, long, help = "Jinja template, html/xml templates are escaped", value_name = "FILE", value_parser = verify_file_exists)]
+    pub template: PathBuf,
+    #[arg(
+        long,
+        help = "Render the template once with `headers` and `rows` instead of once per row"
+    )]
+    pub table: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+}
+
+impl CmdExecutor for CsvRenderOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        crate::process_csv_render(
+            &self.input,
+            &self.output,
+            &self.template,
+            self.table,
+            &self.reader,
+            &mut rejects,
+            &self.types,
+        )?;
+        report_rejects(rejects)
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects, SampleSize};
+
+#[derive(Debug, Parser)]
+pub struct CsvSampleOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(
+        short = 'n',
+        long,
+        help = "Number of rows to keep (per group with --by)",
+        value_name = "N",
+        conflicts_with = "fraction",
+        required_unless_present = "fraction"
+    )]
+    pub rows: Option<usize>,
+    #[arg(
+        long,
+        help = "Share of rows to keep, between 0 and 1 (per group with --by)",
+        value_name = "F"
+    )]
+    pub fraction: Option<f64>,
+    #[arg(
+        long,
+        help = "Stratify: sample every distinct value of this column on its own",
+        value_name = "COLUMN"
+    )]
+    pub by: Option<String>,
+    #[arg(long, help = "Seed for a reproducible sample")]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvSampleOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let size = match (self.rows, self.fraction) {
+            (Some(rows), _) => SampleSize::Rows(rows),
+            (None, Some(fraction)) => SampleSize::Fraction(fraction),
+            (None, None) => anyhow::bail!("Either --rows or --fraction is required"),
+        };
+        let mut rejects = Rejects::default();
+        crate::process_csv_sample(
+            &self.input,
+            &self.output,
+            size,
+            self.by.as_deref(),
+            self.seed,
+            &self.reader,
+            &mut rejects,
+        )?;
+        report_rejects(rejects)
+    }
+}
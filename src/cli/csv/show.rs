@@ -0,0 +1,46 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvFilterOpts, CsvProcessOpts, CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects, TableStyle};
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(long, help = "Only show the first N rows", value_name = "N", conflicts_with = "tail")]
+    pub head: Option<usize>,
+    #[arg(long, help = "Only show the last N rows", value_name = "N")]
+    pub tail: Option<usize>,
+    #[arg(long, help = "Truncate cells wider than this many columns", default_value_t = 30)]
+    pub max_width: usize,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub filter: CsvFilterOpts,
+}
+
+impl CmdExecutor for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        // box drawing is for humans, pipes get plain aligned text
+        let style =
+            if std::io::stdout().is_terminal() { TableStyle::Boxed } else { TableStyle::Plain };
+        let opts =
+            CsvProcessOpts { reader: self.reader, filter: self.filter, ..Default::default() };
+        let mut rejects = Rejects::default();
+        let table = crate::process_csv_show(
+            &self.input,
+            &opts,
+            &mut rejects,
+            self.head,
+            self.tail,
+            self.max_width,
+            style,
+        )?;
+        print!("{}", table);
+        report_rejects(rejects)
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Parser)]
+pub struct CsvShuffleOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    #[arg(long, help = "Seed for a reproducible order")]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvShuffleOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        crate::process_csv_shuffle(
+            &self.input,
+            &self.output,
+            self.seed,
+            &self.reader,
+            &mut rejects,
+        )?;
+        report_rejects(rejects)
+    }
+}
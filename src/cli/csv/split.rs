@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects, SplitBy};
+
+#[derive(Debug, Parser)]
+pub struct CsvSplitOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        long,
+        help = "Rows per output file",
+        value_name = "N",
+        conflicts_with = "by",
+        required_unless_present = "by"
+    )]
+    pub rows: Option<usize>,
+    #[arg(long, help = "Write a file per distinct value of this column", value_name = "COLUMN")]
+    pub by: Option<String>,
+    #[arg(long, help = "Directory for the output files", value_name = "DIR", default_value = ".")]
+    pub dir: PathBuf,
+    #[arg(long, help = "File name prefix, defaults to the input file stem")]
+    pub prefix: Option<String>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let by = match (self.rows, self.by) {
+            (Some(rows), _) => SplitBy::Rows(rows),
+            (None, Some(column)) => SplitBy::Column(column),
+            (None, None) => anyhow::bail!("Either --rows or --by is required"),
+        };
+        let prefix = self.prefix.unwrap_or_else(|| match self.input.to_str() {
+            Some("-") => "split".to_string(),
+            _ => self.input.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        });
+        let mut rejects = Rejects::default();
+        let paths = crate::process_csv_split(
+            &self.input,
+            &self.dir,
+            &prefix,
+            &by,
+            &self.reader,
+            &mut rejects,
+        )?;
+        for path in paths {
+            println!("{}", path.display());
+        }
+        report_rejects(rejects)
+    }
+}
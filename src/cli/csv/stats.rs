@@ -0,0 +1,53 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{
+    CsvColumnarOpts, CsvProcessOpts, CsvReaderOpts, OutputFormat, parse_format, report_rejects,
+};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects, TableStyle};
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Output file, `-` for stdout",
+        value_name = "FILE",
+        default_value = "-"
+    )]
+    pub output: PathBuf,
+    /// a table for humans unless a machine readable format is asked for
+    #[arg(long, help = "Output format: json, yaml, ndjson, toml, md, html, xlsx, parquet or arrow, a table when omitted", value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    #[arg(long, help = "Number of most frequent values to report", default_value_t = 3)]
+    pub top: usize,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub columnar: CsvColumnarOpts,
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let to_terminal = self.output.to_str() == Some("-") && std::io::stdout().is_terminal();
+        let style = if to_terminal { TableStyle::Boxed } else { TableStyle::Plain };
+        let opts =
+            CsvProcessOpts { reader: self.reader, columnar: self.columnar, ..Default::default() };
+        let mut rejects = Rejects::default();
+        crate::process_csv_stats(
+            &self.input,
+            &self.output,
+            &opts,
+            &mut rejects,
+            self.top,
+            self.format,
+            style,
+        )?;
+        report_rejects(rejects)
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+
+use super::{CsvReaderOpts, report_rejects};
+use crate::cli::verify_file_exists;
+use crate::{CmdExecutor, Rejects};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ValidateReport {
+    /// One line per violation
+    Text,
+    /// A json array of violations, for CI
+    Json,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, help = "Input CSV file, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(short, long, help = "Yaml schema describing the expected columns", value_name = "FILE", value_parser = verify_file_exists)]
+    pub schema: PathBuf,
+    #[arg(long, help = "Report format: text or json", default_value = "text", value_parser = parse_report)]
+    pub report: ValidateReport,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut rejects = Rejects::default();
+        let violations =
+            crate::process_csv_validate(&self.input, &self.schema, &self.reader, &mut rejects)?;
+        report_rejects(rejects)?;
+        match self.report {
+            ValidateReport::Json => println!("{}", serde_json::to_string_pretty(&violations)?),
+            ValidateReport::Text => {
+                for v in &violations {
+                    let column = v.column.map(|c| format!(", column {}", c)).unwrap_or_default();
+                    let value =
+                        v.value.as_ref().map(|v| format!(", got '{}'", v)).unwrap_or_default();
+                    println!("row {}{} ({}): {}{}", v.row, column, v.name, v.message, value);
+                }
+            }
+        }
+        // a non-zero exit code lets CI fail on schema drift
+        if !violations.is_empty() {
+            anyhow::bail!("{} schema violation(s) found", violations.len());
+        }
+        Ok(())
+    }
+}
+
+fn parse_report(report: &str) -> Result<ValidateReport, anyhow::Error> {
+    report.parse()
+}
+
+impl FromStr for ValidateReport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ValidateReport::Text),
+            "json" => Ok(ValidateReport::Json),
+            _ => anyhow::bail!("Unsupported report format: {}", s),
+        }
+    }
+}
+
+impl Display for ValidateReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidateReport::Text => write!(f, "text"),
+            ValidateReport::Json => write!(f, "json"),
+        }
+    }
+}
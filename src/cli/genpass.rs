@@ -1,32 +1,226 @@
-use clap::Parser;
+mod check;
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub use check::*;
+use clap::{Parser, Subcommand};
+use enum_dispatch::enum_dispatch;
 use zxcvbn::zxcvbn;
 
-use crate::CmdExecutor;
+use super::verify_file_exists;
+use crate::{
+    CmdExecutor, GeneratedPassword, MAX_PASSPHRASE_WORDS, MAX_PASSWORD_LENGTH, PasswordPolicy,
+};
+
+/// How `genpass` prints its passwords
+#[derive(Debug, Clone, Copy)]
+pub enum GenPassFormat {
+    /// one password per line
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Score existing passwords, one per line, with zxcvbn")]
+    Check(GenPassCheckOpts),
+}
+
+/// A character class of `--classes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordClass {
+    Upper,
+    Lower,
+    Digit,
+    Special,
+}
 
+/// `rcli genpass` generates, `rcli genpass check` scores existing passwords
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
     // 特殊字符 、数字 、大写字母、小写字母
-    #[arg(short, long, default_value_t = 12)]
-    pub length: u8,
-    #[arg(long, default_value_t = true)]
+    #[arg(
+        short,
+        long,
+        help = "Password length, 1 to 4096 (default: 12, 6 with --pin)",
+        value_name = "LENGTH",
+        value_parser = parse_length
+    )]
+    pub length: Option<usize>,
+    /// every class has an on and an off flag, the last one given wins
+    #[arg(long, help = "Include uppercase letters (default)", overrides_with = "no_uppercase")]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, help = "No uppercase letters", overrides_with = "uppercase")]
+    pub no_uppercase: bool,
+    #[arg(long, help = "Include lowercase letters (default)", overrides_with = "no_lowercase")]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long, help = "No lowercase letters", overrides_with = "lowercase")]
+    pub no_lowercase: bool,
+    #[arg(long, help = "Include digits (default)", overrides_with = "no_number")]
     pub number: bool,
-    #[arg(long, default_value_t = false)]
+    #[arg(long, help = "No digits", overrides_with = "number")]
+    pub no_number: bool,
+    #[arg(long, help = "Include special characters", overrides_with = "no_special")]
     pub special: bool,
+    #[arg(long, help = "No special characters (default)", overrides_with = "special")]
+    pub no_special: bool,
+    #[arg(
+        long,
+        help = "Exactly these classes: upper, lower, digit, special",
+        value_name = "CLASSES",
+        value_delimiter = ',',
+        value_parser = parse_class,
+        conflicts_with_all = ["uppercase", "no_uppercase", "lowercase", "no_lowercase", "number", "no_number", "special", "no_special"]
+    )]
+    pub classes: Option<Vec<PasswordClass>>,
+    #[arg(
+        long,
+        help = "A numeric PIN, digits only",
+        conflicts_with_all = ["classes", "charset", "words", "uppercase", "lowercase", "special", "no_number", "min_upper", "min_special"]
+    )]
+    pub pin: bool,
+    #[arg(
+        long,
+        help = "Only use these characters, instead of the character classes",
+        value_name = "CHARS",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["classes", "uppercase", "no_uppercase", "lowercase", "no_lowercase", "number", "no_number", "special", "no_special", "allow_ambiguous"]
+    )]
+    pub charset: Option<String>,
+    #[arg(
+        long,
+        help = "Never use these characters, e.g. '$`\\'",
+        value_name = "CHARS",
+        default_value = "",
+        allow_hyphen_values = true
+    )]
+    pub exclude: String,
+    #[arg(
+        long,
+        help = "Symbols used by --special and --inject-symbol (default: !@#$%^&*_)",
+        value_name = "CHARS",
+        allow_hyphen_values = true
+    )]
+    pub special_set: Option<String>,
+    #[arg(
+        long,
+        help = "Leave out look-alike characters: 0 O 1 I l, also from --charset and --special-set",
+        conflicts_with = "allow_ambiguous"
+    )]
+    pub no_ambiguous: bool,
+    #[arg(long, help = "Put 0 O I l back, the classes leave them out by default")]
+    pub allow_ambiguous: bool,
+    #[arg(
+        long,
+        help = "At least N uppercase letters",
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "no_uppercase"
+    )]
+    pub min_upper: usize,
+    #[arg(
+        long,
+        help = "At least N digits",
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "no_number"
+    )]
+    pub min_digit: usize,
+    #[arg(
+        long,
+        help = "At least N special characters",
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "no_special"
+    )]
+    pub min_special: usize,
+    /// a passphrase is generated instead of a password when given
+    #[arg(
+        long,
+        help = "Generate a diceware passphrase of N words, 1 to 256",
+        value_name = "N",
+        value_parser = parse_words,
+        conflicts_with = "length"
+    )]
+    pub words: Option<usize>,
+    #[arg(long, help = "Separator between passphrase words", default_value = "-")]
+    pub separator: String,
+    #[arg(long, help = "Words to pick from, one per line (default: the EFF large wordlist)", value_name = "FILE", value_parser = verify_file_exists, requires = "words")]
+    pub wordlist: Option<PathBuf>,
+    #[arg(long, help = "Capitalize every passphrase word", requires = "words")]
+    pub capitalize: bool,
+    #[arg(long, help = "Append a random digit to a random passphrase word", requires = "words")]
+    pub inject_digit: bool,
+    #[arg(long, help = "Append a random symbol to a random passphrase word", requires = "words")]
+    pub inject_symbol: bool,
+    #[arg(
+        short,
+        long,
+        help = "Number of passwords to generate",
+        value_name = "N",
+        default_value_t = 1
+    )]
+    pub count: usize,
+    #[arg(
+        long,
+        help = "Generate a password for every line of this file, e.g. user names",
+        value_name = "FILE",
+        value_parser = verify_file_exists,
+        conflicts_with = "count"
+    )]
+    pub labels: Option<PathBuf>,
+    #[arg(long, help = "Output format: text, json or csv (with score and entropy)", value_parser = parse_format, default_value = "text")]
+    pub format: GenPassFormat,
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = crate::process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.special,
-        )?;
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let labels = match &self.labels {
+            Some(path) => std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| Some(line.to_string()))
+                .collect(),
+            None => vec![None; self.count],
+        };
+        if labels.is_empty() {
+            anyhow::bail!("Nothing to generate, --count and --labels need at least one password");
+        }
+        let single = labels.len() == 1 && self.labels.is_none();
+        if !single || !matches!(self.format, GenPassFormat::Text) {
+            let mut passwords = Vec::with_capacity(labels.len());
+            for label in labels {
+                let (password, entropy) = self.generate()?;
+                let score = u8::from(zxcvbn(&password, &[]).score());
+                passwords.push(GeneratedPassword { label, password, score, entropy });
+            }
+            let weak = passwords.iter().filter(|p| p.score < 3).count();
+            if weak > 0 {
+                eprintln!(
+                    "Warning: {} of {} passwords are weak (score < 3)",
+                    weak,
+                    passwords.len()
+                );
+            }
+            print!("{}", crate::format_passwords(&passwords, self.format)?);
+            return Ok(());
+        }
+
+        let (password, entropy) = self.generate()?;
+        if self.words.is_some() {
+            eprintln!("Entropy: {:.1} bits", entropy);
+        }
         let estimate = zxcvbn(&password, &[]);
         if estimate.score().to_string().parse::<u8>().unwrap() < 3 {
             // Put Info to stderr, not affect the stdout for pipe
@@ -41,3 +235,243 @@ impl CmdExecutor for GenPassOpts {
         Ok(())
     }
 }
+
+impl GenPassOpts {
+    /// A password or passphrase as the options ask for, with its entropy in bits
+    fn generate(&self) -> anyhow::Result<(String, f64)> {
+        match self.words {
+            Some(words) => {
+                // the injected characters follow --exclude, --special-set and the look-alike flags
+                let policy = self.policy();
+                let digits = self.inject_digit.then(|| policy.digits());
+                let symbols = self.inject_symbol.then(|| policy.symbols());
+                crate::process_genphrase(
+                    words,
+                    &self.separator,
+                    self.wordlist.as_deref(),
+                    self.capitalize,
+                    digits.as_deref(),
+                    symbols.as_deref(),
+                )
+            }
+            None => {
+                let policy = self.policy();
+                let password = crate::process_genpass(self.length(), &policy)?;
+                Ok((password, policy.entropy(self.length())?))
+            }
+        }
+    }
+
+    fn length(&self) -> usize {
+        match self.length {
+            Some(length) => length,
+            None if self.pin => 6,
+            None => 12,
+        }
+    }
+
+    /// `--pin` and `--classes` replace the per class flags
+    fn has_class(&self, class: PasswordClass) -> bool {
+        if self.pin {
+            return class == PasswordClass::Digit;
+        }
+        if let Some(classes) = &self.classes {
+            return classes.contains(&class);
+        }
+        match class {
+            PasswordClass::Upper => !self.no_uppercase,
+            PasswordClass::Lower => !self.no_lowercase,
+            PasswordClass::Digit => !self.no_number,
+            PasswordClass::Special => self.special,
+        }
+    }
+
+    fn policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            uppercase: self.has_class(PasswordClass::Upper),
+            lowercase: self.has_class(PasswordClass::Lower),
+            number: self.has_class(PasswordClass::Digit),
+            special: self.has_class(PasswordClass::Special),
+            charset: self.charset.clone(),
+            special_set: self.special_set.clone(),
+            exclude: self.exclude.clone(),
+            no_ambiguous: self.no_ambiguous,
+            allow_ambiguous: self.allow_ambiguous,
+            min_upper: self.min_upper,
+            min_digit: self.min_digit,
+            min_special: self.min_special,
+        }
+    }
+}
+
+fn parse_format(format: &str) -> Result<GenPassFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_length(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if (1..=MAX_PASSWORD_LENGTH).contains(&n) => Ok(n),
+        _ => Err(format!("must be a number from 1 to {}", MAX_PASSWORD_LENGTH)),
+    }
+}
+
+fn parse_words(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if (1..=MAX_PASSPHRASE_WORDS).contains(&n) => Ok(n),
+        _ => Err(format!("must be a number from 1 to {}", MAX_PASSPHRASE_WORDS)),
+    }
+}
+
+fn parse_class(class: &str) -> Result<PasswordClass, anyhow::Error> {
+    class.parse()
+}
+
+impl FromStr for GenPassFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(GenPassFormat::Text),
+            "json" => Ok(GenPassFormat::Json),
+            "csv" => Ok(GenPassFormat::Csv),
+            _ => anyhow::bail!("Unsupported format: {}", s),
+        }
+    }
+}
+
+impl Display for GenPassFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenPassFormat::Text => write!(f, "text"),
+            GenPassFormat::Json => write!(f, "json"),
+            GenPassFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for PasswordClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "upper" | "uppercase" => Ok(PasswordClass::Upper),
+            "lower" | "lowercase" => Ok(PasswordClass::Lower),
+            "digit" | "number" => Ok(PasswordClass::Digit),
+            "special" | "symbol" => Ok(PasswordClass::Special),
+            _ => anyhow::bail!("Unsupported character class: {}", s),
+        }
+    }
+}
+
+impl Display for PasswordClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordClass::Upper => write!(f, "upper"),
+            PasswordClass::Lower => write!(f, "lower"),
+            PasswordClass::Digit => write!(f, "digit"),
+            PasswordClass::Special => write!(f, "special"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(args: &[&str]) -> Result<PasswordPolicy, clap::Error> {
+        let opts =
+            GenPassOpts::try_parse_from(std::iter::once("genpass").chain(args.iter().copied()))?;
+        Ok(opts.policy())
+    }
+
+    #[test]
+    fn test_class_flags() -> anyhow::Result<()> {
+        let ret = policy(&[])?;
+        assert!(ret.uppercase && ret.lowercase && ret.number && !ret.special);
+        let ret = policy(&["--no-uppercase", "--no-lowercase"])?;
+        assert!(!ret.uppercase && !ret.lowercase && ret.number);
+        let ret = policy(&["--no-special", "--special", "--no-number"])?;
+        assert!(ret.special && !ret.number);
+        let ret = policy(&["--classes", "lower,digit"])?;
+        assert!(!ret.uppercase && ret.lowercase && ret.number && !ret.special);
+        assert!(policy(&["--classes", "lower", "--uppercase"]).is_err());
+        assert!(policy(&["--classes", "emoji"]).is_err());
+        assert!(policy(&["--no-number", "--min-digit", "2"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_charset() -> anyhow::Result<()> {
+        let ret = policy(&["--charset", "abc", "--min-upper", "0"])?;
+        assert_eq!(ret.charset.as_deref(), Some("abc"));
+        for flag in ["--special", "--no-number", "--uppercase", "--allow-ambiguous"] {
+            assert!(policy(&["--charset", "abc", flag]).is_err());
+        }
+        assert!(policy(&["--charset", "abc", "--classes", "lower"]).is_err());
+        assert!(policy(&["--no-ambiguous", "--allow-ambiguous"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_inject() -> anyhow::Result<()> {
+        let args = ["genpass", "--words", "3", "--inject-digit", "--inject-symbol"];
+        let opts = GenPassOpts::try_parse_from(args.into_iter().chain(["--exclude", "$!@#%^&*1"]))?;
+        for _ in 0..20 {
+            let (phrase, _) = opts.generate()?;
+            assert!(!phrase.contains(|c| "$!@#%^&*1".contains(c)));
+            assert_eq!(phrase.matches('_').count(), 1);
+        }
+        let opts = GenPassOpts::try_parse_from(args.into_iter().chain(["--special-set", "-"]))?;
+        let (phrase, _) = opts.generate()?;
+        assert_eq!(phrase.matches('-').count(), 3);
+        let opts = GenPassOpts::try_parse_from(args.into_iter().chain(["--exclude", "!@#$%^&*_"]))?;
+        assert!(opts.generate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin() -> anyhow::Result<()> {
+        let opts = GenPassOpts::try_parse_from(["genpass", "--pin"])?;
+        assert_eq!(opts.length(), 6);
+        let ret = opts.policy();
+        assert!(!ret.uppercase && !ret.lowercase && ret.number && !ret.special);
+        let opts = GenPassOpts::try_parse_from(["genpass", "--pin", "-l", "300"])?;
+        assert_eq!(opts.length(), 300);
+        assert!(GenPassOpts::try_parse_from(["genpass", "--pin", "--special"]).is_err());
+        assert!(GenPassOpts::try_parse_from(["genpass", "--pin", "--no-number"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_limit() -> anyhow::Result<()> {
+        let opts = GenPassOpts::try_parse_from(["genpass", "-l", "4096"])?;
+        assert_eq!(opts.length(), MAX_PASSWORD_LENGTH);
+        assert!(GenPassOpts::try_parse_from(["genpass", "-l", "4097"]).is_err());
+        assert!(GenPassOpts::try_parse_from(["genpass", "-l", "0"]).is_err());
+        assert!(GenPassOpts::try_parse_from(["genpass", "-l", "18446744073709551615"]).is_err());
+        assert!(GenPassOpts::try_parse_from(["genpass", "--words", "256"]).is_ok());
+        assert!(GenPassOpts::try_parse_from(["genpass", "--words", "1000000000"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let opts = GenPassOpts::try_parse_from([
+            "genpass",
+            "check",
+            "--user-input",
+            "alice,alice@example.com",
+            "--min-score",
+            "3",
+        ])?;
+        let Some(GenPassSubCommand::Check(check)) = opts.cmd else {
+            anyhow::bail!("expected the check subcommand");
+        };
+        assert_eq!(check.user_input, ["alice", "alice@example.com"]);
+        assert_eq!(check.min_score, Some(3));
+        assert_eq!(check.input, PathBuf::from("-"));
+        assert!(GenPassOpts::try_parse_from(["genpass", "check", "--min-score", "5"]).is_err());
+        assert!(GenPassOpts::try_parse_from(["genpass", "-l", "16", "check"]).is_err());
+        Ok(())
+    }
+}
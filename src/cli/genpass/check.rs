@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{GenPassFormat, parse_format};
+use crate::CmdExecutor;
+use crate::cli::verify_file_exists;
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, help = "Passwords to check, one per line, `-` for stdin", value_name = "FILE", value_parser = verify_file_exists, default_value = "-")]
+    pub input: PathBuf,
+    #[arg(
+        long,
+        help = "Words an attacker would try first, e.g. the user name and email",
+        value_name = "WORDS",
+        value_delimiter = ','
+    )]
+    pub user_input: Vec<String>,
+    #[arg(long, help = "Fail if any password scores below this (0-4)", value_name = "SCORE", value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+    #[arg(long, help = "Output format: text, json or csv", value_parser = parse_format, default_value = "text")]
+    pub format: GenPassFormat,
+}
+
+impl CmdExecutor for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let checks = crate::process_genpass_check(&self.input, &self.user_input)?;
+        print!("{}", crate::format_checks(&checks, self.format)?);
+        if let Some(min_score) = self.min_score {
+            let failed = checks.iter().filter(|c| c.score < min_score).count();
+            if failed > 0 {
+                anyhow::bail!(
+                    "{} of {} password(s) score below {}",
+                    failed,
+                    checks.len(),
+                    min_score
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use clap::Parser;
 use rcli::{CmdExecutor, Opts};
 
-// rcli csv -i input.csv -o output.json --header -d ','
+// rcli csv -i input.csv -o output.json -d ','
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
@@ -1,11 +1,52 @@
 mod b64;
+mod csv_cat;
+mod csv_clean;
+mod csv_columnar;
 mod csv_convert;
+mod csv_diff;
+mod csv_encoding;
+mod csv_error;
+mod csv_filter;
+mod csv_import;
+mod csv_infer;
+mod csv_join;
+mod csv_nested;
+mod csv_query;
+mod csv_reader;
+mod csv_render;
+mod csv_sample;
+mod csv_show;
+mod csv_split;
+mod csv_stats;
+mod csv_validate;
+mod csv_writer;
+mod csv_xlsx;
+mod gen_batch;
+mod gen_check;
 mod gen_pass;
+mod gen_phrase;
 mod http_serve;
 mod text;
 
 pub use b64::{process_decode, process_encode};
+pub use csv_cat::process_csv_cat;
+pub use csv_clean::process_csv_clean;
 pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_diff::{CellChange, CsvDiff, RowChange, process_csv_diff};
+pub use csv_error::CsvError;
+pub use csv_import::process_csv_import;
+pub use csv_join::process_csv_join;
+pub use csv_query::process_csv_query;
+pub use csv_reader::Rejects;
+pub use csv_render::process_csv_render;
+pub use csv_sample::{SampleSize, process_csv_sample, process_csv_shuffle};
+pub use csv_show::{TableStyle, process_csv_show};
+pub use csv_split::{SplitBy, process_csv_split};
+pub use csv_stats::{ColumnStats, TopValue, process_csv_stats};
+pub use csv_validate::{ColumnRule, CsvSchema, Violation, process_csv_validate};
+pub use gen_batch::{GeneratedPassword, format_passwords};
+pub use gen_check::{CrackTimes, PasswordCheck, format_checks, process_genpass_check};
+pub use gen_pass::{MAX_PASSWORD_LENGTH, PasswordPolicy, process_genpass};
+pub use gen_phrase::{MAX_PASSPHRASE_WORDS, process_genphrase};
 pub use http_serve::process_http_serve;
 pub use text::{process_text_generate, process_text_sign, process_text_verify};
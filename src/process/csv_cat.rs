@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::csv_reader::{
+    Rejects, build_reader, build_writer, read_headers, read_records, writes_headers,
+};
+use crate::CsvReaderOpts;
+
+/// Concatenate csv files into `output`. Columns are matched by name, the header is
+/// the union of all headers in first seen order and missing cells are filled with `missing`
+pub fn process_csv_cat(
+    inputs: &[PathBuf],
+    output: &Path,
+    missing: &str,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    // headers come from a first pass, so only one input is open at a time
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    let mut all_headers = Vec::with_capacity(inputs.len());
+    let mut stdin = None;
+    for input in inputs {
+        let mut reader = build_reader(input, opts)?;
+        let file_headers = read_headers(&mut reader, opts)?;
+        for name in &file_headers {
+            if seen.insert(name.to_string()) {
+                headers.push(name.to_string());
+            }
+        }
+        // stdin can only be read once, its reader is kept for the second pass
+        if input.to_str() == Some("-") {
+            stdin = Some(reader);
+        }
+        all_headers.push(file_headers);
+    }
+
+    let mut writer = build_writer(output, opts)?;
+    if writes_headers(opts) {
+        writer.write_record(&headers)?;
+    }
+    for (input, file_headers) in inputs.iter().zip(all_headers) {
+        let reader = match stdin.take() {
+            Some(reader) if input.to_str() == Some("-") => reader,
+            kept => {
+                stdin = kept;
+                let mut reader = build_reader(input, opts)?;
+                read_headers(&mut reader, opts)?;
+                reader
+            }
+        };
+        let positions =
+            file_headers.iter().enumerate().map(|(i, h)| (h, i)).collect::<HashMap<_, _>>();
+        let layout = headers.iter().map(|h| positions.get(h.as_str()).copied()).collect::<Vec<_>>();
+        for record in read_records(reader, input, opts, rejects)? {
+            let record = record?;
+            writer.write_record(
+                layout.iter().map(|i| i.and_then(|i| record.get(i)).unwrap_or(missing)),
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_cat_aligns_headers() -> anyhow::Result<()> {
+        let mut first = NamedTempFile::new()?;
+        first.write_all(b"name,city\nAlice,Paris\n")?;
+        let mut second = NamedTempFile::new()?;
+        second.write_all(b"age,name\n30,Bob\n")?;
+        let output = NamedTempFile::new()?;
+        let inputs = vec![first.path().to_path_buf(), second.path().to_path_buf()];
+        process_csv_cat(
+            &inputs,
+            output.path(),
+            "NA",
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        assert_eq!(
+            std::fs::read_to_string(output.path())?,
+            "name,city,age\nAlice,Paris,NA\nBob,NA,30\n"
+        );
+        Ok(())
+    }
+}
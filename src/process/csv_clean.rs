@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use csv::StringRecord;
+use unicode_normalization::UnicodeNormalization;
+
+use super::csv_infer::parse_date;
+use super::csv_reader::{
+    Rejects, build_reader, build_writer, column_index, read_headers, read_records, writes_headers,
+};
+use crate::{CsvCleanRules, CsvReaderOpts};
+
+/// Per column cleanup, resolved against the header
+#[derive(Debug, Clone, Copy, Default)]
+struct ColumnRules {
+    lower: bool,
+    title: bool,
+    date: bool,
+}
+
+/// Clean `input` into `output`, every modified cell and dropped row is logged to `log`
+pub fn process_csv_clean(
+    input: &Path,
+    output: &Path,
+    rules: &CsvCleanRules,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+    log: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let mut columns = vec![ColumnRules::default(); headers.len()];
+    for name in &rules.lower {
+        columns[column_index(&headers, name)?].lower = true;
+    }
+    for name in &rules.title {
+        columns[column_index(&headers, name)?].title = true;
+    }
+    for name in &rules.dates {
+        columns[column_index(&headers, name)?].date = true;
+    }
+    let dedup_key = rules
+        .dedup_by
+        .as_ref()
+        .map(|names| {
+            names.iter().map(|n| column_index(&headers, n)).collect::<anyhow::Result<Vec<_>>>()
+        })
+        .transpose()?;
+
+    let mut writer = build_writer(output, opts)?;
+    if writes_headers(opts) {
+        writer.write_record(&headers)?;
+    }
+    // key of every kept row, with the line it was first seen on
+    let mut seen: HashMap<Vec<String>, u64> = HashMap::new();
+    let (mut changed, mut dropped) = (0, 0);
+    for record in read_records(reader, input, opts, rejects)? {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        let mut cleaned = StringRecord::with_capacity(record.as_slice().len(), record.len());
+        for (idx, value) in record.iter().enumerate() {
+            let column = columns.get(idx).copied().unwrap_or_default();
+            let (value, steps) = clean_value(value, rules, column);
+            if !steps.is_empty() {
+                changed += 1;
+                let name = headers.get(idx).unwrap_or_default();
+                writeln!(
+                    log,
+                    "line {}, column '{}' ({}): {:?} -> {:?}",
+                    line,
+                    name,
+                    steps.join(", "),
+                    &record[idx],
+                    value
+                )?;
+            }
+            cleaned.push_field(&value);
+        }
+
+        if !rules.keep_empty && cleaned.iter().all(str::is_empty) {
+            dropped += 1;
+            writeln!(log, "line {}: dropped empty row", line)?;
+            continue;
+        }
+        if rules.dedup || dedup_key.is_some() {
+            let key = match &dedup_key {
+                Some(indices) => indices
+                    .iter()
+                    .map(|&i| cleaned.get(i).unwrap_or_default().to_string())
+                    .collect(),
+                None => cleaned.iter().map(String::from).collect(),
+            };
+            if let Some(first) = seen.get(&key) {
+                dropped += 1;
+                writeln!(log, "line {}: dropped duplicate of line {}", line, first)?;
+                continue;
+            }
+            seen.insert(key, line);
+        }
+        writer.write_record(&cleaned)?;
+    }
+    writer.flush()?;
+    writeln!(log, "{} values changed, {} rows dropped", changed, dropped)?;
+    Ok(())
+}
+
+/// The cleaned value along with the names of the steps that changed it
+fn clean_value(
+    value: &str,
+    rules: &CsvCleanRules,
+    column: ColumnRules,
+) -> (String, Vec<&'static str>) {
+    let mut steps = Vec::new();
+    let mut value = value.to_string();
+    let mut apply = |value: &mut String, step: &'static str, next: String| {
+        if *value != next {
+            *value = next;
+            steps.push(step);
+        }
+    };
+    if !rules.keep_whitespace {
+        let next = value.trim().to_string();
+        apply(&mut value, "trim", next);
+    }
+    if !rules.no_nfc {
+        let next = value.nfc().collect();
+        apply(&mut value, "nfc", next);
+    }
+    if column.lower {
+        let next = value.to_lowercase();
+        apply(&mut value, "lower", next);
+    }
+    if column.title {
+        let next = title_case(&value);
+        apply(&mut value, "title", next);
+    }
+    if column.date {
+        // values which are not dates are left alone
+        if let Some(date) = parse_date(&value) {
+            let next = date.format("%Y-%m-%d").to_string();
+            apply(&mut value, "date", next);
+        }
+    }
+    (value, steps)
+}
+
+/// Uppercase the first letter of every word and lowercase the rest,
+/// words are separated by anything but letters, digits and apostrophes
+fn title_case(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    let mut word_start = true;
+    for c in value.chars() {
+        if word_start {
+            ret.extend(c.to_uppercase());
+        } else {
+            ret.extend(c.to_lowercase());
+        }
+        word_start = !(c.is_alphanumeric() || c == '\'');
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn clean(content: &str, rules: &CsvCleanRules) -> anyhow::Result<(String, String)> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        let output = NamedTempFile::new()?;
+        let mut log = Vec::new();
+        process_csv_clean(
+            input.path(),
+            output.path(),
+            rules,
+            &Default::default(),
+            &mut Default::default(),
+            &mut log,
+        )?;
+        Ok((std::fs::read_to_string(output.path())?, String::from_utf8(log)?))
+    }
+
+    #[test]
+    fn test_clean_values() -> anyhow::Result<()> {
+        let rules = CsvCleanRules {
+            lower: vec!["email".into()],
+            title: vec!["position".into()],
+            dates: vec!["dob".into()],
+            ..Default::default()
+        };
+        // the second name is `e` followed by a combining acute accent
+        let content = "name,email,position,dob\n  Cafe\u{301} ,Bob@X.io,centre-back,\"Jan 19, 1978 (41)\"\n,,,\nZoe,z@x.io,GOALKEEPER,unknown\n";
+        let (output, log) = clean(content, &rules)?;
+        assert_eq!(
+            output,
+            "name,email,position,dob\nCafé,bob@x.io,Centre-Back,1978-01-19\nZoe,z@x.io,Goalkeeper,unknown\n"
+        );
+        let log = log.lines().collect::<Vec<_>>();
+        // debug quoting escapes the combining accent, so the nfc change is visible
+        assert_eq!(log[0], "line 2, column 'name' (trim, nfc): \"  Cafe\\u{301} \" -> \"Café\"");
+        assert_eq!(log[3], "line 2, column 'dob' (date): \"Jan 19, 1978 (41)\" -> \"1978-01-19\"");
+        assert_eq!(log[4], "line 3: dropped empty row");
+        assert_eq!(log.last(), Some(&"5 values changed, 1 rows dropped"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_dedup() -> anyhow::Result<()> {
+        let content = "id,name\n1,Alice\n1, Alice\n1,Bob\n";
+        let (output, log) = clean(content, &CsvCleanRules { dedup: true, ..Default::default() })?;
+        assert_eq!(output, "id,name\n1,Alice\n1,Bob\n");
+        assert!(log.contains("line 3: dropped duplicate of line 2"));
+
+        let rules = CsvCleanRules { dedup_by: Some(vec!["id".into()]), ..Default::default() };
+        assert_eq!(clean(content, &rules)?.0, "id,name\n1,Alice\n");
+        assert!(
+            clean(content, &CsvCleanRules { lower: vec!["x".into()], ..Default::default() })
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_title_case() {
+        assert_eq!(title_case("centre-BACK"), "Centre-Back");
+        assert_eq!(title_case("o'neil van dijk"), "O'neil Van Dijk");
+    }
+}
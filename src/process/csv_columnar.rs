@@ -0,0 +1,446 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::types::Date32Type;
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, RecordBatch, StringArray,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+
+use super::csv_writer::{RecordWriter, cell_text};
+use crate::CsvColumnType;
+use crate::cli::{ColumnarCompression, CsvColumnarOpts, OutputFormat};
+
+/// Writes parquet or arrow ipc files, buffering `row_group_size` records into one record batch.
+/// Parquet also ends a row group after every batch
+pub struct ColumnarWriter {
+    format: OutputFormat,
+    opts: CsvColumnarOpts,
+    headers: Vec<String>,
+    /// column types resolved while reading the csv, `None` infers them from the first batch
+    types: Option<Vec<CsvColumnType>>,
+    inferred: bool,
+    rows: Vec<Value>,
+    /// number of records written in previous batches, for error reporting
+    written: usize,
+    writer: Option<Box<dyn Write>>,
+    sink: Option<Sink>,
+}
+
+enum Sink {
+    /// `ArrowWriter` needs a `Send` writer, so every row group is staged in memory
+    Parquet(ArrowWriter<Vec<u8>>, Box<dyn Write>),
+    Arrow(FileWriter<Box<dyn Write>>),
+}
+
+impl ColumnarWriter {
+    pub fn new(
+        format: OutputFormat,
+        writer: Box<dyn Write>,
+        headers: Vec<String>,
+        types: Option<Vec<CsvColumnType>>,
+        opts: CsvColumnarOpts,
+    ) -> Self {
+        Self {
+            format,
+            opts,
+            headers,
+            inferred: types.is_none(),
+            types,
+            rows: Vec::new(),
+            written: 0,
+            writer: Some(writer),
+            sink: None,
+        }
+    }
+
+    fn write_batch(&mut self) -> anyhow::Result<()> {
+        let types = match &self.types {
+            Some(types) => types.clone(),
+            None => self.infer_types(),
+        };
+        // the first batch fixes the schema of the whole file
+        self.types = Some(types.clone());
+        let schema = self.schema(&types);
+        let columns = types
+            .iter()
+            .enumerate()
+            .map(|(col, ty)| self.column(col, *ty))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        self.written += self.rows.len();
+        self.rows.clear();
+
+        match self.sink(schema)? {
+            Sink::Parquet(writer, out) => {
+                writer.write(&batch)?;
+                writer.flush()?;
+                writer.sync()?;
+                out.write_all(&std::mem::take(writer.inner_mut()))?;
+            }
+            Sink::Arrow(writer) => writer.write(&batch)?,
+        }
+        Ok(())
+    }
+
+    fn schema(&self, types: &[CsvColumnType]) -> SchemaRef {
+        let fields = self
+            .headers
+            .iter()
+            .zip(types)
+            .map(|(name, ty)| Field::new(name, data_type(*ty), true))
+            .collect::<Vec<_>>();
+        Arc::new(Schema::new(fields))
+    }
+
+    fn sink(&mut self, schema: SchemaRef) -> anyhow::Result<&mut Sink> {
+        if self.sink.is_none() {
+            let writer = self.writer.take().expect("the writer is only taken once");
+            let sink = match self.format {
+                OutputFormat::Parquet => {
+                    let props = WriterProperties::builder()
+                        .set_compression(self.opts.compression.into())
+                        .set_max_row_group_row_count(Some(self.opts.row_group_size))
+                        .build();
+                    Sink::Parquet(ArrowWriter::try_new(Vec::new(), schema, Some(props))?, writer)
+                }
+                _ => Sink::Arrow(FileWriter::try_new(writer, &schema)?),
+            };
+            self.sink = Some(sink);
+        }
+        Ok(self.sink.as_mut().expect("the sink was just opened"))
+    }
+
+    /// Build the array of column `col`, every value has to fit `ty`
+    fn column(&self, col: usize, ty: CsvColumnType) -> anyhow::Result<ArrayRef> {
+        let name = &self.headers[col];
+        let values = self.rows.iter().map(|row| row.get(name).unwrap_or(&Value::Null));
+        let mismatch = |idx: usize, value: &Value| {
+            if self.inferred {
+                return anyhow::anyhow!(
+                    "Value {} in column '{}' of record {} does not fit type {} inferred from the first row group, raise --row-group-size to infer it from more rows",
+                    value,
+                    name,
+                    self.written + idx + 1,
+                    ty
+                );
+            }
+            // an integer column widened by a late float can not change its written row groups
+            if ty == CsvColumnType::Int && value.is_f64() {
+                return anyhow::anyhow!(
+                    "Value {} in column '{}' of record {} does not fit type int, use --type \"{}=float\" to store the column as floats",
+                    value,
+                    name,
+                    self.written + idx + 1,
+                    name
+                );
+            }
+            anyhow::anyhow!(
+                "Value {} in column '{}' of record {} does not fit type {}, use --type \"{}=string\" to keep the column as text",
+                value,
+                name,
+                self.written + idx + 1,
+                ty,
+                name
+            )
+        };
+        let array: ArrayRef = match ty {
+            CsvColumnType::Int => Arc::new(
+                values
+                    .enumerate()
+                    .map(|(idx, v)| match v {
+                        Value::Null => Ok(None),
+                        _ => v.as_i64().map(Some).ok_or_else(|| mismatch(idx, v)),
+                    })
+                    .collect::<anyhow::Result<Int64Array>>()?,
+            ),
+            CsvColumnType::Float => Arc::new(
+                values
+                    .enumerate()
+                    .map(|(idx, v)| match v {
+                        Value::Null => Ok(None),
+                        _ => v.as_f64().map(Some).ok_or_else(|| mismatch(idx, v)),
+                    })
+                    .collect::<anyhow::Result<Float64Array>>()?,
+            ),
+            CsvColumnType::Bool => Arc::new(
+                values
+                    .enumerate()
+                    .map(|(idx, v)| match v {
+                        Value::Null => Ok(None),
+                        _ => v.as_bool().map(Some).ok_or_else(|| mismatch(idx, v)),
+                    })
+                    .collect::<anyhow::Result<BooleanArray>>()?,
+            ),
+            // dates are converted to `YYYY-MM-DD` strings
+            CsvColumnType::Date => Arc::new(
+                values
+                    .enumerate()
+                    .map(|(idx, v)| match v {
+                        Value::Null => Ok(None),
+                        _ => v
+                            .as_str()
+                            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+                            .map(|d| Some(Date32Type::from_naive_date(d)))
+                            .ok_or_else(|| mismatch(idx, v)),
+                    })
+                    .collect::<anyhow::Result<Date32Array>>()?,
+            ),
+            // nested values of --unflatten are kept as json text
+            CsvColumnType::String => Arc::new(
+                values
+                    .map(|v| match v {
+                        Value::Null => None,
+                        _ => Some(cell_text(v)),
+                    })
+                    .collect::<StringArray>(),
+            ),
+        };
+        Ok(array)
+    }
+
+    /// Column types from the values of the current batch, anything mixed becomes a string
+    fn infer_types(&self) -> Vec<CsvColumnType> {
+        self.headers
+            .iter()
+            .map(|name| {
+                let mut ret = None;
+                for value in self.rows.iter().filter_map(|row| row.get(name)) {
+                    let ty = match value {
+                        Value::Null => continue,
+                        Value::Bool(_) => CsvColumnType::Bool,
+                        Value::Number(n) if n.is_i64() => CsvColumnType::Int,
+                        Value::Number(_) => CsvColumnType::Float,
+                        _ => CsvColumnType::String,
+                    };
+                    ret = match (ret, ty) {
+                        (None, ty) => Some(ty),
+                        (Some(a), b) if a == b => Some(a),
+                        (Some(CsvColumnType::Int), CsvColumnType::Float)
+                        | (Some(CsvColumnType::Float), CsvColumnType::Int) => {
+                            Some(CsvColumnType::Float)
+                        }
+                        _ => Some(CsvColumnType::String),
+                    };
+                }
+                ret.unwrap_or(CsvColumnType::String)
+            })
+            .collect()
+    }
+}
+
+impl RecordWriter for ColumnarWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        self.rows.push(record.clone());
+        if self.rows.len() >= self.opts.row_group_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if !self.rows.is_empty() {
+            self.write_batch()?;
+        }
+        if self.sink.is_none() {
+            // an empty input still gets a file holding the schema
+            let types =
+                self.types.clone().unwrap_or(vec![CsvColumnType::String; self.headers.len()]);
+            let schema = self.schema(&types);
+            self.sink(schema)?;
+        }
+        match self.sink.take().expect("the sink is open") {
+            Sink::Parquet(writer, mut out) => {
+                out.write_all(&writer.into_inner()?)?;
+                out.flush()?;
+            }
+            Sink::Arrow(mut writer) => {
+                writer.finish()?;
+                writer.get_mut().flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn data_type(ty: CsvColumnType) -> DataType {
+    match ty {
+        CsvColumnType::Int => DataType::Int64,
+        CsvColumnType::Float => DataType::Float64,
+        CsvColumnType::Bool => DataType::Boolean,
+        CsvColumnType::Date => DataType::Date32,
+        CsvColumnType::String => DataType::Utf8,
+    }
+}
+
+impl From<ColumnarCompression> for Compression {
+    fn from(compression: ColumnarCompression) -> Self {
+        match compression {
+            ColumnarCompression::None => Compression::UNCOMPRESSED,
+            ColumnarCompression::Snappy => Compression::SNAPPY,
+            ColumnarCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{
+        CsvProcessOpts, CsvQueryTable, CsvTypeOpts, TableStyle, process_csv, process_csv_query,
+        process_csv_stats,
+    };
+
+    fn convert(
+        format: OutputFormat,
+        type_opts: &CsvTypeOpts,
+        columnar: &CsvColumnarOpts,
+    ) -> anyhow::Result<NamedTempFile> {
+        let output = NamedTempFile::new()?;
+        let opts = CsvProcessOpts {
+            types: type_opts.clone(),
+            columnar: columnar.clone(),
+            ..Default::default()
+        };
+        process_csv(
+            Path::new("assets/juventus.csv"),
+            output.path(),
+            format,
+            false,
+            &opts,
+            &mut Default::default(),
+        )?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_parquet_row_groups() -> anyhow::Result<()> {
+        let columnar =
+            CsvColumnarOpts { row_group_size: 10, compression: ColumnarCompression::Zstd };
+        let output = convert(OutputFormat::Parquet, &Default::default(), &columnar)?;
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(output.path())?)?;
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 27);
+        let schema = builder.schema().clone();
+        assert_eq!(schema.field_with_name("Name")?.data_type(), &DataType::Utf8);
+        assert_eq!(schema.field_with_name("Kit Number")?.data_type(), &DataType::Int64);
+
+        let batch = builder.build()?.next().unwrap()?;
+        let kits = batch.column(4).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(kits.value(0), 1);
+        assert_eq!(kits.value(1), 37);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_ipc() -> anyhow::Result<()> {
+        let type_opts = CsvTypeOpts { no_infer: true, ..Default::default() };
+        let output = convert(OutputFormat::Arrow, &type_opts, &Default::default())?;
+
+        let reader = FileReader::try_new(File::open(output.path())?, None)?;
+        assert_eq!(reader.schema().field(0).name(), "Name");
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 27);
+        let names = batches[0].column(0).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(names.value(0), "Wojciech Szczesny");
+        Ok(())
+    }
+
+    #[test]
+    fn test_columnar_types() -> anyhow::Result<()> {
+        let output = NamedTempFile::new()?;
+        let headers = vec!["id".to_string(), "joined".to_string(), "score".to_string()];
+        let types = vec![CsvColumnType::Int, CsvColumnType::Date, CsvColumnType::Float];
+        let mut writer = Box::new(ColumnarWriter::new(
+            OutputFormat::Parquet,
+            Box::new(File::create(output.path())?),
+            headers.clone(),
+            Some(types.clone()),
+            Default::default(),
+        ));
+        writer.write_record(&serde_json::json!({"id": 1, "joined": "1970-01-02", "score": 2}))?;
+        writer.write_record(&serde_json::json!({"id": null, "joined": null, "score": 0.5}))?;
+        writer.finish()?;
+
+        let batch =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(output.path())?)?.build()?.next();
+        let batch = batch.unwrap()?;
+        let dates = batch.column(1).as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!(dates.value(0), 1);
+        assert!(dates.is_null(1));
+        let scores = batch.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(scores.value(0), 2.0);
+
+        let mut writer = Box::new(ColumnarWriter::new(
+            OutputFormat::Parquet,
+            Box::new(Vec::new()),
+            headers,
+            Some(types),
+            Default::default(),
+        ));
+        writer.write_record(&serde_json::json!({"id": "x", "joined": null, "score": null}))?;
+        let err = writer.finish().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Value \"x\" in column 'id' of record 1 does not fit type int, use --type \"id=string\" to keep the column as text"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_and_stats_layout() -> anyhow::Result<()> {
+        let columnar =
+            CsvColumnarOpts { row_group_size: 2, compression: ColumnarCompression::Zstd };
+        let opts = CsvProcessOpts { columnar: columnar.clone(), ..Default::default() };
+        let input = Path::new("assets/juventus.csv");
+        let output = NamedTempFile::new()?;
+        let tables = [CsvQueryTable { name: "players".to_string(), path: input.to_path_buf() }];
+        let sql = "SELECT Name, \"Kit Number\" FROM players LIMIT 5";
+        process_csv_query(
+            sql,
+            &tables,
+            output.path(),
+            &opts,
+            &mut Default::default(),
+            Some(OutputFormat::Parquet),
+            TableStyle::Plain,
+        )?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(output.path())?)?;
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let column = builder.metadata().row_group(0).column(1);
+        assert_eq!(column.compression(), columnar.compression.into());
+        assert_eq!(builder.schema().field(1).data_type(), &DataType::Int64);
+
+        process_csv_stats(
+            input,
+            output.path(),
+            &opts,
+            &mut Default::default(),
+            1,
+            Some(OutputFormat::Parquet),
+            TableStyle::Plain,
+        )?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(output.path())?)?;
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let schema = builder.schema().clone();
+        assert_eq!(schema.field_with_name("count")?.data_type(), &DataType::Int64);
+        assert_eq!(schema.field_with_name("min")?.data_type(), &DataType::Utf8);
+        assert_eq!(schema.field_with_name("mean")?.data_type(), &DataType::Float64);
+        Ok(())
+    }
+}
@@ -1,12 +1,15 @@
-use std::path::PathBuf;
+use std::path::Path;
 
-use csv::Reader;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 
+use super::csv_filter::RecordPipeline;
+use super::csv_infer::infer_converter;
+use super::csv_nested::{self, unflatten_headers};
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use super::csv_writer::record_writer;
 use crate::cli::OutputFormat;
+use crate::{CsvProcessOpts, get_writer};
 
-const CSV_CAPACITY: usize = 128;
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
@@ -20,21 +23,251 @@ struct Player {
     kit: u8,
 }
 
-pub fn process_csv(input: &PathBuf, output: &PathBuf, format: OutputFormat) -> anyhow::Result<()> {
-    let mut reader = Reader::from_path(input).expect("Failed to open CSV file");
+/// Stream `input` (a file or `-` for stdin) into `output`.
+/// Column types are inferred from the first records only, so memory stays constant.
+/// Parquet and arrow columns take the same types, `opts.columnar` sets their row groups and compression
+pub fn process_csv(
+    input: &Path,
+    output: &Path,
+    format: OutputFormat,
+    unflatten: bool,
+    opts: &CsvProcessOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    let mut reader = build_reader(input, &opts.reader)?;
+    let headers = read_headers(&mut reader, &opts.reader)?;
+    let pipeline = RecordPipeline::new(&headers, &opts.filter)?;
+    let headers = pipeline.headers().clone();
+    let records = pipeline.apply(read_records(reader, input, &opts.reader, rejects)?);
+    let (converter, records) = infer_converter(records, &headers, &opts.types)?;
 
-    let mut ret = Vec::with_capacity(CSV_CAPACITY);
-    let headers = reader.headers().expect("Failed to read headers").clone();
+    let table_headers = if unflatten { unflatten_headers(&headers) } else { headers };
+    let writer = get_writer(output)?;
+    // unflattened columns hold nested values, so their types come from the values instead
+    let types = (!unflatten).then(|| converter.types().to_vec());
+    let mut writer = record_writer(format, writer, &table_headers, types, &opts.columnar);
     // support more generic csv files
-    for record in reader.records() {
-        let record = record.expect("Failed to read record");
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
-    }
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret).expect("Failed to serialize to YAML"),
-    };
-    std::fs::write(output, content).expect("Failed to write output file");
-    Ok(())
+    for (idx, record) in records.enumerate() {
+        let mut json_value = converter.convert(&record?, idx)?;
+        if unflatten {
+            json_value = csv_nested::unflatten(&json_value)?;
+        }
+        writer.write_record(&json_value)?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::Value;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CsvColumnType, CsvReaderOpts, CsvTrim, CsvTypeOpts, CsvTypeOverride};
+
+    fn convert(content: &str, opts: &CsvReaderOpts) -> anyhow::Result<Value> {
+        convert_typed(content, opts, &CsvTypeOpts { no_infer: true, ..Default::default() })
+    }
+
+    fn convert_typed(
+        content: &str,
+        opts: &CsvReaderOpts,
+        type_opts: &CsvTypeOpts,
+    ) -> anyhow::Result<Value> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        let output = NamedTempFile::new()?;
+        let opts =
+            CsvProcessOpts { reader: opts.clone(), types: type_opts.clone(), ..Default::default() };
+        process_csv(
+            input.path(),
+            output.path(),
+            OutputFormat::Json,
+            false,
+            &opts,
+            &mut Default::default(),
+        )?;
+        Ok(serde_json::from_str(&std::fs::read_to_string(output.path())?)?)
+    }
+
+    #[test]
+    fn test_process_csv_delimiter() -> anyhow::Result<()> {
+        let opts = CsvReaderOpts { delimiter: b';', ..Default::default() };
+        let value = convert("name;city\nAlice;Paris\n", &opts)?;
+        assert_eq!(value, serde_json::json!([{"name": "Alice", "city": "Paris"}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_without_header() -> anyhow::Result<()> {
+        let opts = CsvReaderOpts { no_header: true, delimiter: b'\t', ..Default::default() };
+        let value = convert("Alice\tParis\nBob\tRome\n", &opts)?;
+        assert_eq!(
+            value,
+            serde_json::json!([
+                {"col0": "Alice", "col1": "Paris"},
+                {"col0": "Bob", "col1": "Rome"},
+            ])
+        );
+
+        let opts = CsvReaderOpts {
+            no_header: true,
+            columns: Some(vec!["name".into(), "city".into()]),
+            ..Default::default()
+        };
+        let value = convert("Alice,Paris\n", &opts)?;
+        assert_eq!(value, serde_json::json!([{"name": "Alice", "city": "Paris"}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_quote_comment_trim() -> anyhow::Result<()> {
+        let opts = CsvReaderOpts {
+            quote: b'\'',
+            comment: Some(b'#'),
+            trim: CsvTrim::All,
+            ..Default::default()
+        };
+        let value = convert("# exported\nname , motto\n Alice ,'a, b'\n", &opts)?;
+        assert_eq!(value, serde_json::json!([{"name": "Alice", "motto": "a, b"}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_infer_types() -> anyhow::Result<()> {
+        let content = "name,kit,height,active,joined,note\nAlice,10,1.70,true,2020-01-31,\n";
+        let value = convert_typed(content, &CsvReaderOpts::default(), &CsvTypeOpts::default())?;
+        assert_eq!(
+            value,
+            serde_json::json!([{
+                "name": "Alice",
+                "kit": 10,
+                "height": 1.7,
+                "active": true,
+                "joined": "2020-01-31",
+                "note": null,
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_type_overrides() -> anyhow::Result<()> {
+        let type_opts = CsvTypeOpts {
+            no_infer: true,
+            types: vec![
+                CsvTypeOverride { column: "Kit Number".into(), ty: CsvColumnType::Int },
+                CsvTypeOverride { column: "DOB".into(), ty: CsvColumnType::Date },
+            ],
+            ..Default::default()
+        };
+        let value = convert_typed(
+            &std::fs::read_to_string("assets/juventus.csv")?,
+            &CsvReaderOpts::default(),
+            &type_opts,
+        )?;
+        assert_eq!(value[0]["Kit Number"], 1);
+        assert_eq!(value[0]["DOB"], "1990-04-18");
+
+        let type_opts = CsvTypeOpts {
+            types: vec![CsvTypeOverride { column: "Name".into(), ty: CsvColumnType::Int }],
+            ..Default::default()
+        };
+        let err = convert_typed("Name\nAlice\n", &CsvReaderOpts::default(), &type_opts);
+        assert!(err.is_err());
+        Ok(())
+    }
+
+    fn convert_juventus(format: OutputFormat) -> anyhow::Result<String> {
+        let output = NamedTempFile::new()?;
+        let input = Path::new("assets/juventus.csv");
+        process_csv(
+            input,
+            output.path(),
+            format,
+            false,
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        Ok(std::fs::read_to_string(output.path())?)
+    }
+
+    #[test]
+    fn test_process_csv_ndjson() -> anyhow::Result<()> {
+        let content = convert_juventus(OutputFormat::Ndjson)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 27);
+        let first: Value = serde_json::from_str(lines[0])?;
+        assert_eq!(first["Name"], "Wojciech Szczesny");
+        assert_eq!(first["Kit Number"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_toml() -> anyhow::Result<()> {
+        let content = convert_juventus(OutputFormat::Toml)?;
+        let value: toml::Table = toml::from_str(&content)?;
+        let rows = value["rows"].as_array().unwrap();
+        assert_eq!(rows.len(), 27);
+        assert_eq!(rows[1]["Name"].as_str(), Some("Mattia Perin"));
+        assert_eq!(rows[1]["Kit Number"].as_integer(), Some(37));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_markdown() -> anyhow::Result<()> {
+        let content = convert_juventus(OutputFormat::Markdown)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 29);
+        assert_eq!(lines[0], "| Name | Position | DOB | Nationality | Kit Number |");
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- |");
+        assert_eq!(lines[2], "| Wojciech Szczesny | Goalkeeper | Apr 18, 1990 (29) | Poland | 1 |");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_html() -> anyhow::Result<()> {
+        let content = convert_juventus(OutputFormat::Html)?;
+        assert!(content.starts_with("<!DOCTYPE html>"));
+        assert!(content.contains("<tr><th>Name</th><th>Position</th><th>DOB</th><th>Nationality</th><th>Kit Number</th></tr>"));
+        assert!(content.contains("<tr><td>Wojciech Szczesny</td><td>Goalkeeper</td><td>Apr 18, 1990 (29)</td><td>Poland</td><td>1</td></tr>"));
+        assert_eq!(content.matches("<tr>").count(), 28);
+        assert!(content.trim_end().ends_with("</html>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_unflatten() -> anyhow::Result<()> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(b"id,address.city,address.zip,tags[0],tags[1]\n1,Turin,10121,a,b\n")?;
+        let output = NamedTempFile::new()?;
+        let opts = CsvProcessOpts::default();
+        process_csv(
+            input.path(),
+            output.path(),
+            OutputFormat::Json,
+            true,
+            &opts,
+            &mut Default::default(),
+        )?;
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(output.path())?)?;
+        assert_eq!(
+            value,
+            serde_json::json!([{"id": 1, "address": {"city": "Turin", "zip": 10121}, "tags": ["a", "b"]}])
+        );
+
+        process_csv(
+            input.path(),
+            output.path(),
+            OutputFormat::Markdown,
+            true,
+            &opts,
+            &mut Default::default(),
+        )?;
+        let content = std::fs::read_to_string(output.path())?;
+        assert!(content.starts_with("| id | address | tags |\n"));
+        Ok(())
+    }
 }
@@ -0,0 +1,380 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+use csv::{StringRecord, WriterBuilder};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::csv_reader::{Rejects, build_reader, key_indices, read_headers, read_records};
+use crate::{CsvReaderOpts, DiffFormat, get_writer};
+
+/// Keyed difference between two snapshots of a csv file
+#[derive(Debug, Serialize)]
+pub struct CsvDiff {
+    pub key: Vec<String>,
+    /// columns only present in the new file
+    pub added_columns: Vec<String>,
+    /// columns only present in the old file
+    pub removed_columns: Vec<String>,
+    pub rows: Vec<RowChange>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum RowChange {
+    Added { key: Map<String, Value>, row: Map<String, Value> },
+    Removed { key: Map<String, Value>, row: Map<String, Value> },
+    Changed { key: Map<String, Value>, cells: Vec<CellChange> },
+}
+
+#[derive(Debug, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A csv file loaded for diffing, records are kept in file order
+/// Records of a snapshot by their key values
+type KeyIndex<'a> = HashMap<Vec<String>, &'a StringRecord>;
+
+struct Snapshot {
+    headers: StringRecord,
+    key_idx: Vec<usize>,
+    records: Vec<StringRecord>,
+}
+
+/// Compare `old` and `new` by the `key` columns and write the changes to `output`.
+/// Text is colored when it goes to a terminal
+pub fn process_csv_diff(
+    old: &Path,
+    new: &Path,
+    output: &Path,
+    key: &[String],
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+    format: DiffFormat,
+) -> anyhow::Result<()> {
+    // the key is also a map from column to value, a repeated column would collapse in it
+    if let Some(k) = key.iter().enumerate().find_map(|(i, k)| key[..i].contains(k).then_some(k)) {
+        anyhow::bail!("Key column '{}' is given more than once", k);
+    }
+    let old = Snapshot::load(old, key, opts, rejects)?;
+    let new = Snapshot::load(new, key, opts, rejects)?;
+    let new_index = new.index()?;
+    let diff = diff_snapshots(&old, &new, &new_index, key)?;
+    let mut writer = get_writer(output)?;
+    match format {
+        DiffFormat::Text => {
+            let color = output.to_str() == Some("-") && std::io::stdout().is_terminal();
+            writer.write_all(diff_text(&diff, color).as_bytes())?
+        }
+        DiffFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &diff)?;
+            writeln!(writer)?;
+        }
+        DiffFormat::Csv => write_patch(&mut writer, &diff, &old, &new, &new_index, opts)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl Snapshot {
+    fn load(
+        input: &Path,
+        key: &[String],
+        opts: &CsvReaderOpts,
+        rejects: &mut Rejects,
+    ) -> anyhow::Result<Self> {
+        let mut reader = build_reader(input, opts)?;
+        let headers = read_headers(&mut reader, opts)?;
+        let key_idx = key_indices(&headers, key, input)?;
+        let records =
+            read_records(reader, input, opts, rejects)?.collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { headers, key_idx, records })
+    }
+
+    fn key_of(&self, record: &StringRecord) -> Vec<String> {
+        self.key_idx.iter().map(|&i| record.get(i).unwrap_or_default().to_string()).collect()
+    }
+
+    /// Records by key, a key must identify a single row
+    fn index(&self) -> anyhow::Result<KeyIndex<'_>> {
+        let mut ret = HashMap::with_capacity(self.records.len());
+        for record in &self.records {
+            let key = self.key_of(record);
+            if ret.contains_key(&key) {
+                anyhow::bail!(
+                    "Duplicate key '{}' on line {}",
+                    key.join(", "),
+                    record.position().map_or(0, |p| p.line())
+                );
+            }
+            ret.insert(key, record);
+        }
+        Ok(ret)
+    }
+
+    fn to_map(&self, record: &StringRecord) -> Map<String, Value> {
+        self.headers.iter().zip(record.iter()).map(|(h, v)| (h.to_string(), v.into())).collect()
+    }
+}
+
+/// Removed and changed rows come in the order of the old file, added rows in the order of the new one
+fn diff_snapshots(
+    old: &Snapshot,
+    new: &Snapshot,
+    new_index: &KeyIndex,
+    key: &[String],
+) -> anyhow::Result<CsvDiff> {
+    let old_index = old.index()?;
+    let key_map = |values: &[String]| {
+        key.iter().cloned().zip(values.iter().map(|v| Value::from(v.as_str()))).collect()
+    };
+    // cells are compared by column name, so reordered columns are not a change
+    let common = old
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| new.headers.iter().position(|n| n == h).map(|j| (h, i, j)))
+        .collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    for record in &old.records {
+        let values = old.key_of(record);
+        match new_index.get(&values) {
+            None => {
+                rows.push(RowChange::Removed { key: key_map(&values), row: old.to_map(record) })
+            }
+            Some(other) => {
+                let cells = common
+                    .iter()
+                    .filter_map(|&(column, i, j)| {
+                        let (before, after) =
+                            (record.get(i).unwrap_or_default(), other.get(j).unwrap_or_default());
+                        (before != after).then(|| CellChange {
+                            column: column.to_string(),
+                            old: before.to_string(),
+                            new: after.to_string(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                if !cells.is_empty() {
+                    rows.push(RowChange::Changed { key: key_map(&values), cells });
+                }
+            }
+        }
+    }
+    for record in &new.records {
+        let values = new.key_of(record);
+        if !old_index.contains_key(&values) {
+            rows.push(RowChange::Added { key: key_map(&values), row: new.to_map(record) });
+        }
+    }
+
+    let missing_from = |a: &StringRecord, b: &StringRecord| {
+        a.iter().filter(|h| !b.iter().any(|x| x == *h)).map(String::from).collect()
+    };
+    Ok(CsvDiff {
+        key: key.to_vec(),
+        added_columns: missing_from(&new.headers, &old.headers),
+        removed_columns: missing_from(&old.headers, &new.headers),
+        rows,
+    })
+}
+
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+
+/// Wrap `text` in an ansi color when `color` is set
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text.to_string() }
+}
+
+/// A line per row, `+` added, `-` removed and `~` changed followed by its `old → new` cells
+fn diff_text(diff: &CsvDiff, color: bool) -> String {
+    let key_text = |key: &Map<String, Value>| {
+        key.values().map(|v| v.as_str().unwrap_or_default()).collect::<Vec<_>>().join(", ")
+    };
+    let row_text = |key: &Map<String, Value>, row: &Map<String, Value>| {
+        row.iter()
+            .filter(|(column, _)| !key.contains_key(*column))
+            .map(|(column, value)| format!("{}={}", column, value.as_str().unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut ret = String::new();
+    for column in &diff.added_columns {
+        writeln!(ret, "{}", paint(&format!("+ column {}", column), GREEN, color)).unwrap();
+    }
+    for column in &diff.removed_columns {
+        writeln!(ret, "{}", paint(&format!("- column {}", column), RED, color)).unwrap();
+    }
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for change in &diff.rows {
+        match change {
+            RowChange::Added { key, row } => {
+                added += 1;
+                let line = format!("+ {}: {}", key_text(key), row_text(key, row));
+                writeln!(ret, "{}", paint(&line, GREEN, color)).unwrap();
+            }
+            RowChange::Removed { key, row } => {
+                removed += 1;
+                let line = format!("- {}: {}", key_text(key), row_text(key, row));
+                writeln!(ret, "{}", paint(&line, RED, color)).unwrap();
+            }
+            RowChange::Changed { key, cells } => {
+                changed += 1;
+                writeln!(ret, "{}", paint(&format!("~ {}", key_text(key)), YELLOW, color)).unwrap();
+                for cell in cells {
+                    writeln!(
+                        ret,
+                        "    {}: {} → {}",
+                        cell.column,
+                        paint(&cell.old, RED, color),
+                        paint(&cell.new, GREEN, color)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    writeln!(ret, "{} added, {} removed, {} changed", added, removed, changed).unwrap();
+    ret
+}
+
+/// A wide csv with an `_op` column (added/removed/changed) in front of the new header.
+/// Added and changed rows hold the full new row, removed rows the old one
+fn write_patch(
+    writer: &mut dyn Write,
+    diff: &CsvDiff,
+    old: &Snapshot,
+    new: &Snapshot,
+    new_index: &KeyIndex,
+    opts: &CsvReaderOpts,
+) -> anyhow::Result<()> {
+    let mut headers = new.headers.iter().collect::<Vec<_>>();
+    let seen = headers.iter().copied().collect::<HashSet<_>>();
+    headers.extend(old.headers.iter().filter(|h| !seen.contains(h)));
+
+    // written in the dialect of the inputs, so the patch reads back with the same options
+    let mut writer =
+        WriterBuilder::new().delimiter(opts.delimiter).quote(opts.quote).from_writer(writer);
+    writer.write_record(std::iter::once("_op").chain(headers.iter().copied()))?;
+    for change in &diff.rows {
+        let (op, values) = match change {
+            RowChange::Added { row, .. } => ("added", row.clone()),
+            RowChange::Removed { row, .. } => ("removed", row.clone()),
+            RowChange::Changed { key, .. } => {
+                let values = key
+                    .values()
+                    .map(|v| v.as_str().unwrap_or_default().to_string())
+                    .collect::<Vec<_>>();
+                let Some(record) = new_index.get(&values) else {
+                    anyhow::bail!(
+                        "Changed row '{}' is missing from the new file",
+                        values.join(", ")
+                    );
+                };
+                ("changed", new.to_map(record))
+            }
+        };
+        let cells = headers.iter().map(|h| values.get(*h).and_then(Value::as_str).unwrap_or(""));
+        writer.write_record(std::iter::once(op).chain(cells))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn diff(old: &str, new: &str, key: &[&str], format: DiffFormat) -> anyhow::Result<String> {
+        diff_with(old, new, key, format, &Default::default())
+    }
+
+    fn diff_with(
+        old: &str,
+        new: &str,
+        key: &[&str],
+        format: DiffFormat,
+        opts: &CsvReaderOpts,
+    ) -> anyhow::Result<String> {
+        let mut old_file = NamedTempFile::new()?;
+        old_file.write_all(old.as_bytes())?;
+        let mut new_file = NamedTempFile::new()?;
+        new_file.write_all(new.as_bytes())?;
+        let output = NamedTempFile::new()?;
+        let key = key.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        process_csv_diff(
+            old_file.path(),
+            new_file.path(),
+            output.path(),
+            &key,
+            opts,
+            &mut Default::default(),
+            format,
+        )?;
+        Ok(std::fs::read_to_string(output.path())?)
+    }
+
+    const OLD: &str =
+        "Name,Position,Kit\nBuffon,Goalkeeper,77\nPogba,Midfielder,6\nDybala,Forward,21\n";
+    const NEW: &str = "Name,Kit,Position,Age\nBuffon,1,Goalkeeper,41\nDybala,21,Forward,25\nRonaldo,7,Forward,34\n";
+
+    #[test]
+    fn test_diff_text() -> anyhow::Result<()> {
+        let ret = diff(OLD, NEW, &["Name"], DiffFormat::Text)?;
+        assert_eq!(
+            ret,
+            "+ column Age\n~ Buffon\n    Kit: 77 → 1\n- Pogba: Position=Midfielder, Kit=6\n+ Ronaldo: Kit=7, Position=Forward, Age=34\n1 added, 1 removed, 1 changed\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_patch_and_json() -> anyhow::Result<()> {
+        let ret = diff(OLD, NEW, &["Name"], DiffFormat::Csv)?;
+        assert_eq!(
+            ret,
+            "_op,Name,Kit,Position,Age\nchanged,Buffon,1,Goalkeeper,41\nremoved,Pogba,6,Midfielder,\nadded,Ronaldo,7,Forward,34\n"
+        );
+
+        let ret: Value = serde_json::from_str(&diff(OLD, NEW, &["Name"], DiffFormat::Json)?)?;
+        assert_eq!(ret["rows"][0]["op"], "changed");
+        assert_eq!(ret["rows"][0]["cells"][0]["old"], "77");
+        assert_eq!(ret["rows"][1]["key"]["Name"], "Pogba");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_patch_quote() -> anyhow::Result<()> {
+        let opts = CsvReaderOpts { delimiter: b';', quote: b'\'', ..Default::default() };
+        let old = "id;name\n1;'a;b'\n";
+        let new = "id;name\n1;'a;c'\n";
+        let ret = diff_with(old, new, &["id"], DiffFormat::Csv, &opts)?;
+        assert_eq!(ret, "_op;id;name\nchanged;1;'a;c'\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_composite_key() -> anyhow::Result<()> {
+        let old = "team,kit,name\nA,1,x\nB,1,y\n";
+        let new = "team,kit,name\nA,1,x\nB,1,z\n";
+        let ret = diff(old, new, &["team", "kit"], DiffFormat::Text)?;
+        assert_eq!(ret, "~ B, 1\n    name: y → z\n0 added, 0 removed, 1 changed\n");
+
+        assert!(diff(old, new, &["kit"], DiffFormat::Text).is_err());
+        assert!(diff(old, new, &["missing"], DiffFormat::Text).is_err());
+        let err = diff(old, new, &["name", "name"], DiffFormat::Csv).unwrap_err();
+        assert_eq!(err.to_string(), "Key column 'name' is given more than once");
+        Ok(())
+    }
+}
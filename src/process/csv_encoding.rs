@@ -0,0 +1,249 @@
+use std::io::{self, Cursor, Read};
+
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{DecoderResult, Encoding, UTF_8};
+
+/// Number of leading bytes used to guess the encoding
+const DETECT_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Wrap `reader` so it yields utf-8. A BOM wins over `encoding`, which wins over detection.
+/// The BOM is always stripped, utf-8 input is passed through untouched
+pub fn decode_reader(
+    mut reader: Box<dyn Read>,
+    encoding: Option<&'static Encoding>,
+    has_headers: bool,
+    quote: u8,
+) -> anyhow::Result<Box<dyn Read>> {
+    let mut sample = Vec::with_capacity(DETECT_SAMPLE_SIZE);
+    reader.by_ref().take(DETECT_SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    let is_last = sample.len() < DETECT_SAMPLE_SIZE;
+
+    let (encoding, bom_len) = match Encoding::for_bom(&sample) {
+        Some((bom, len)) => (bom, len),
+        None => (encoding.unwrap_or_else(|| detect(&sample, is_last)), 0),
+    };
+    let rest = Cursor::new(sample).chain(reader);
+    let mut rest: Box<dyn Read> = Box::new(rest);
+    io::copy(&mut rest.by_ref().take(bom_len as u64), &mut io::sink())?;
+    if encoding == UTF_8 {
+        // the csv reader reports invalid utf-8 with its record and byte offset by itself
+        return Ok(rest);
+    }
+    Ok(Box::new(DecodingReader::new(rest, encoding, bom_len as u64, has_headers, quote)))
+}
+
+/// Valid utf-8 is taken as such, anything else is up to chardetng.
+/// A few bad bytes among mostly valid multi-byte chars keep utf-8, so the csv reader
+/// reports them with their record instead of the whole file turning into mojibake
+fn detect(sample: &[u8], is_last: bool) -> &'static Encoding {
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        // a multi-byte char cut off by the end of the sample
+        Err(e) if e.error_len().is_none() && !is_last => UTF_8,
+        Err(_) if mostly_utf8(sample) => UTF_8,
+        Err(_) => {
+            let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+            detector.feed(sample, is_last);
+            detector.guess(None, Utf8Detection::Allow)
+        }
+    }
+}
+
+/// Whether most non-ascii bytes belong to valid utf-8 chars. Legacy encodings only form
+/// valid utf-8 by chance, so their invalid bytes far outnumber the valid ones
+fn mostly_utf8(sample: &[u8]) -> bool {
+    let (mut valid, mut invalid) = (0, 0);
+    for chunk in sample.utf8_chunks() {
+        valid += chunk.valid().bytes().filter(|b| !b.is_ascii()).count();
+        invalid += chunk.invalid().len();
+    }
+    valid > 0 && valid >= invalid
+}
+
+/// Streams `inner` decoded from `encoding` to utf-8, failing on malformed bytes.
+/// Records are counted along the way so errors can tell where they are
+struct DecodingReader {
+    inner: Box<dyn Read>,
+    encoding: &'static Encoding,
+    decoder: encoding_rs::Decoder,
+    input: Vec<u8>,
+    input_start: usize,
+    input_end: usize,
+    output: Vec<u8>,
+    output_start: usize,
+    output_end: usize,
+    /// offset in the original input of `input[input_start]`
+    offset: u64,
+    eof: bool,
+    finished: bool,
+    position: RecordPosition,
+}
+
+/// Where the decoded output currently is in csv terms
+struct RecordPosition {
+    quote: u8,
+    in_quotes: bool,
+    line: u64,
+    record: u64,
+    has_headers: bool,
+}
+
+impl DecodingReader {
+    fn new(
+        inner: Box<dyn Read>,
+        encoding: &'static Encoding,
+        offset: u64,
+        has_headers: bool,
+        quote: u8,
+    ) -> Self {
+        Self {
+            inner,
+            encoding,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            input: vec![0; 8 * 1024],
+            input_start: 0,
+            input_end: 0,
+            output: vec![0; 32 * 1024],
+            output_start: 0,
+            output_end: 0,
+            offset,
+            eof: false,
+            finished: false,
+            position: RecordPosition { quote, in_quotes: false, line: 1, record: 0, has_headers },
+        }
+    }
+
+    /// Decode the next chunk into `output`, returns false once everything is decoded
+    fn fill(&mut self) -> io::Result<bool> {
+        while !self.finished {
+            if self.input_start == self.input_end && !self.eof {
+                let n = self.inner.read(&mut self.input)?;
+                self.offset += self.input_start as u64;
+                self.input_start = 0;
+                self.input_end = n;
+                self.eof = n == 0;
+            }
+            let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+                &self.input[self.input_start..self.input_end],
+                &mut self.output,
+                self.eof,
+            );
+            self.position.observe(&self.output[..written]);
+            match result {
+                DecoderResult::Malformed(bad, extra) => {
+                    let at =
+                        self.offset + (self.input_start + read) as u64 - extra as u64 - bad as u64;
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Invalid {} bytes at byte offset {} ({})",
+                            self.encoding.name(),
+                            at,
+                            self.position
+                        ),
+                    ));
+                }
+                DecoderResult::InputEmpty if self.eof => self.finished = true,
+                _ => {}
+            }
+            self.input_start += read;
+            if written > 0 {
+                self.output_start = 0;
+                self.output_end = written;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Read for DecodingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output_start == self.output_end && !self.fill()? {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.output_end - self.output_start);
+        buf[..n].copy_from_slice(&self.output[self.output_start..self.output_start + n]);
+        self.output_start += n;
+        Ok(n)
+    }
+}
+
+impl RecordPosition {
+    fn observe(&mut self, decoded: &[u8]) {
+        for &b in decoded {
+            if b == self.quote {
+                // a doubled quote toggles twice, so it stays inside the field
+                self.in_quotes = !self.in_quotes;
+            } else if b == b'\n' {
+                self.line += 1;
+                if !self.in_quotes {
+                    self.record += 1;
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RecordPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.has_headers, self.record) {
+            (true, 0) => write!(f, "header, line {}", self.line),
+            (true, record) => write!(f, "record {}, line {}", record, self.line),
+            (false, record) => write!(f, "record {}, line {}", record + 1, self.line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{GBK, SHIFT_JIS, WINDOWS_1252};
+
+    use super::*;
+
+    fn decode(bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> anyhow::Result<String> {
+        let mut ret = String::new();
+        decode_reader(Box::new(Cursor::new(bytes)), encoding, true, b'"')?
+            .read_to_string(&mut ret)?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_decode_explicit_and_bom() -> anyhow::Result<()> {
+        let text = "name,city\n张三,北京\n";
+        assert_eq!(decode(GBK.encode(text).0.into_owned(), Some(GBK))?, text);
+
+        let text = "name,city\n山田,東京\n";
+        assert_eq!(decode(SHIFT_JIS.encode(text).0.into_owned(), Some(SHIFT_JIS))?, text);
+
+        // utf-16 has no encoder in encoding_rs, the BOM wins over the wrong label
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("name\nZoë\n".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert_eq!(decode(utf16, Some(WINDOWS_1252))?, "name\nZoë\n");
+
+        let mut utf8 = b"\xEF\xBB\xBFname\n".to_vec();
+        utf8.extend(b"Zo\xC3\xAB\n");
+        assert_eq!(decode(utf8, None)?, "name\nZoë\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() {
+        let text = "name,city\nJosé,Málaga\nFrançois,Besançon\nZoë,Köln\n".repeat(20);
+        assert_eq!(detect(&WINDOWS_1252.encode(&text).0, true), WINDOWS_1252);
+        assert_eq!(detect(text.as_bytes(), true), UTF_8);
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.extend(b"Pogb\xFF\n");
+        assert_eq!(detect(&bytes, true), UTF_8);
+        let text = "姓名,城市\n张三,北京\n李四,上海\n王五,广州\n".repeat(20);
+        assert_eq!(detect(&GBK.encode(&text).0, true), GBK);
+    }
+
+    #[test]
+    fn test_malformed_bytes() {
+        // 0x81 alone is not valid in shift_jis
+        let bytes = b"name,note\na,\"x\ny\"\nb,\x81\n".to_vec();
+        let err = decode(bytes, Some(SHIFT_JIS)).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid Shift_JIS bytes at byte offset 20 (record 2, line 4)");
+    }
+}
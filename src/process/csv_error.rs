@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use thiserror::Error;
+
+/// A malformed csv record, located in its input.
+/// `record` is 1-based and does not count the header row
+#[derive(Debug, Error)]
+pub enum CsvError {
+    #[error("{path}: line {line}, record {record}: found {found} fields, expected {expected}")]
+    UnequalLengths { path: String, line: u64, record: u64, expected: usize, found: usize },
+    #[error(
+        "{path}: line {line}, record {record}, field {field} ({name}): invalid UTF-8 after {valid_up_to} bytes"
+    )]
+    InvalidUtf8 {
+        path: String,
+        line: u64,
+        record: u64,
+        field: usize,
+        name: String,
+        valid_up_to: usize,
+    },
+    /// the input could not be parsed any further, e.g. unreadable or badly encoded bytes
+    #[error("{path}: {error}")]
+    Read { path: String, error: csv::Error },
+    /// the rejects file of `--skip-bad-rows` could not be written
+    #[error("{path}: cannot write rejected rows: {error}")]
+    Write { path: String, error: csv::Error },
+}
+
+/// How an input shows up in error messages
+pub fn display_path(path: &Path) -> String {
+    match path.to_str() {
+        Some("-") => "<stdin>".to_string(),
+        _ => path.display().to_string(),
+    }
+}
@@ -0,0 +1,484 @@
+use std::cmp::Ordering;
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+
+use super::csv_infer::parse_date;
+use super::csv_reader::column_index;
+use crate::CsvFilterOpts;
+
+/// Applies `--where`, `--sort`, `--limit` and `--select` to a stream of records.
+/// Without `--sort` records flow through one at a time, sorting has to buffer them all
+#[derive(Debug)]
+pub struct RecordPipeline {
+    headers: StringRecord,
+    select: Option<Vec<usize>>,
+    predicates: Vec<Expr>,
+    sort: Option<(usize, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// `~=`, substring match
+    Contains,
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Column(usize),
+    Literal(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Cmp(Operand, CmpOp, Operand),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Op(CmpOp),
+    And,
+    Or,
+    Not,
+    /// a quoted string, always a literal
+    Str(String),
+    /// byte span of a bare word, adjacent words form one operand such as `Kit Number`
+    Word(usize, usize),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    headers: &'a StringRecord,
+}
+
+impl RecordPipeline {
+    pub fn new(headers: &StringRecord, opts: &CsvFilterOpts) -> anyhow::Result<Self> {
+        let select = opts
+            .select
+            .as_ref()
+            .map(|cols| {
+                cols.iter().map(|c| column_index(headers, c)).collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?;
+        let predicates = opts
+            .filter
+            .iter()
+            .map(|e| parse_expr(e, headers))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let sort = opts.sort.as_ref().map(|c| column_index(headers, c)).transpose()?;
+        let output_headers = match &select {
+            Some(select) => select.iter().map(|i| &headers[*i]).collect(),
+            None => headers.clone(),
+        };
+        Ok(Self {
+            headers: output_headers,
+            select,
+            predicates,
+            sort: sort.map(|idx| (idx, opts.desc)),
+            limit: opts.limit,
+        })
+    }
+
+    /// Column names of the records produced by `apply`
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    pub fn apply<'a, I>(
+        &'a self,
+        records: I,
+    ) -> Box<dyn Iterator<Item = anyhow::Result<StringRecord>> + 'a>
+    where
+        I: Iterator<Item = anyhow::Result<StringRecord>> + 'a,
+    {
+        let filtered = records.filter(|r| r.as_ref().map_or(true, |r| self.matches(r)));
+        let rows: Box<dyn Iterator<Item = anyhow::Result<StringRecord>>> = match self.sort {
+            Some((idx, desc)) => match filtered.collect::<anyhow::Result<Vec<_>>>() {
+                Ok(rows) => {
+                    // every cell is classified once, ties keep their input order
+                    let mut keyed = rows
+                        .into_iter()
+                        .map(|r| (SortKey::new(r.get(idx).unwrap_or_default()), r))
+                        .collect::<Vec<_>>();
+                    keyed.sort_by(|(a, _), (b, _)| {
+                        let ord = a.compare(b);
+                        if desc { ord.reverse() } else { ord }
+                    });
+                    Box::new(keyed.into_iter().map(|(_, r)| Ok(r)))
+                }
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            },
+            None => Box::new(filtered),
+        };
+        Box::new(rows.take(self.limit.unwrap_or(usize::MAX)).map(|r| r.map(|r| self.project(r))))
+    }
+
+    fn matches(&self, record: &StringRecord) -> bool {
+        self.predicates.iter().all(|e| e.eval(record))
+    }
+
+    fn project(&self, record: StringRecord) -> StringRecord {
+        match &self.select {
+            Some(select) => select.iter().map(|i| record.get(*i).unwrap_or_default()).collect(),
+            None => record,
+        }
+    }
+}
+
+/// A cell as it compares: numbers before dates before text, each kind by value
+#[derive(Debug)]
+enum SortKey {
+    Number(f64),
+    Date(NaiveDate),
+    Text(String),
+}
+
+impl SortKey {
+    fn new(value: &str) -> Self {
+        match value.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => SortKey::Number(n),
+            _ => match parse_date(value) {
+                Some(date) => SortKey::Date(date),
+                None => SortKey::Text(value.to_string()),
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SortKey::Number(_) => "number",
+            SortKey::Date(_) => "date",
+            SortKey::Text(_) => "text",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SortKey::Number(_) => 0,
+            SortKey::Date(_) => 1,
+            SortKey::Text(_) => 2,
+        }
+    }
+
+    /// A total order, so sorting a column of mixed kinds is well defined
+    fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            // both are finite, so they always compare
+            (SortKey::Number(a), SortKey::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (SortKey::Date(a), SortKey::Date(b)) => a.cmp(b),
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// Numbers and dates compare by value, anything else as text.
+/// Values of different kinds order numbers first, then dates, then text
+pub fn compare_values(a: &str, b: &str) -> Ordering {
+    SortKey::new(a).compare(&SortKey::new(b))
+}
+
+/// Compare two values of the same kind, or return the kind of `b` when `a` is not of it
+pub fn compare_same_kind(a: &str, b: &str) -> Result<Ordering, &'static str> {
+    let (a, b) = (SortKey::new(a), SortKey::new(b));
+    if a.rank() == b.rank() { Ok(a.compare(&b)) } else { Err(b.kind()) }
+}
+
+impl Expr {
+    fn eval(&self, record: &StringRecord) -> bool {
+        match self {
+            Expr::Cmp(l, op, r) => {
+                let (a, b) = (l.value(record), r.value(record));
+                match op {
+                    CmpOp::Contains => a.contains(b),
+                    CmpOp::Eq => compare_values(a, b).is_eq(),
+                    CmpOp::Ne => compare_values(a, b).is_ne(),
+                    CmpOp::Gt => compare_values(a, b).is_gt(),
+                    CmpOp::Ge => compare_values(a, b).is_ge(),
+                    CmpOp::Lt => compare_values(a, b).is_lt(),
+                    CmpOp::Le => compare_values(a, b).is_le(),
+                }
+            }
+            Expr::And(l, r) => l.eval(record) && r.eval(record),
+            Expr::Or(l, r) => l.eval(record) || r.eval(record),
+            Expr::Not(e) => !e.eval(record),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, record: &'a StringRecord) -> &'a str {
+        match self {
+            Operand::Column(idx) => record.get(*idx).unwrap_or_default(),
+            Operand::Literal(s) => s,
+        }
+    }
+}
+
+/// Parse a `--where` expression: comparisons (`== != > >= < <= ~=`) between columns and
+/// literals, combined with `&&`/`and`, `||`/`or`, `!`/`not` and parentheses.
+/// A bare word naming a column is that column, anything else (or anything quoted) is a literal
+fn parse_expr(input: &str, headers: &StringRecord) -> anyhow::Result<Expr> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { input, tokens, pos: 0, headers };
+    let expr = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        anyhow::bail!("Unexpected trailing input in expression '{}'", input);
+    }
+    Ok(expr)
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    const SPECIAL: &[char] = &['(', ')', '"', '\'', '=', '!', '<', '>', '~', '&', '|'];
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match (c, next) {
+            _ if c.is_whitespace() => continue,
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('"' | '\'', _) => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => s.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, q)) if q == c => break,
+                        Some((_, q)) => s.push(q),
+                        None => anyhow::bail!("Unterminated string in expression '{}'", input),
+                    }
+                }
+                Token::Str(s)
+            }
+            ('=', Some('='))
+            | ('!', Some('='))
+            | ('>', Some('='))
+            | ('<', Some('='))
+            | ('~', Some('=')) => {
+                chars.next();
+                Token::Op(match c {
+                    '=' => CmpOp::Eq,
+                    '!' => CmpOp::Ne,
+                    '>' => CmpOp::Ge,
+                    '<' => CmpOp::Le,
+                    _ => CmpOp::Contains,
+                })
+            }
+            ('=', _) => Token::Op(CmpOp::Eq),
+            ('>', _) => Token::Op(CmpOp::Gt),
+            ('<', _) => Token::Op(CmpOp::Lt),
+            ('!', _) => Token::Not,
+            ('&', Some('&')) | ('|', Some('|')) => {
+                chars.next();
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            _ if SPECIAL.contains(&c) => {
+                anyhow::bail!("Unexpected '{}' in expression '{}'", c, input)
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.peek() {
+                    if c.is_whitespace() || SPECIAL.contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                match input[start..end].to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(start, end),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> anyhow::Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                if self.next() != Some(Token::RParen) {
+                    anyhow::bail!("Missing ')' in expression '{}'", self.input);
+                }
+                Ok(expr)
+            }
+            _ => self.parse_cmp(),
+        }
+    }
+
+    fn parse_cmp(&mut self) -> anyhow::Result<Expr> {
+        let left = self.parse_operand()?;
+        let Some(Token::Op(op)) = self.next() else {
+            anyhow::bail!("Expected a comparison operator in expression '{}'", self.input);
+        };
+        let right = self.parse_operand()?;
+        if !matches!(left, Operand::Column(_)) && !matches!(right, Operand::Column(_)) {
+            anyhow::bail!("No known column in comparison of expression '{}'", self.input);
+        }
+        Ok(Expr::Cmp(left, op, right))
+    }
+
+    fn parse_operand(&mut self) -> anyhow::Result<Operand> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Operand::Literal(s)),
+            Some(Token::Word(start, mut end)) => {
+                while let Some(Token::Word(_, e)) = self.peek() {
+                    end = *e;
+                    self.pos += 1;
+                }
+                let word = &self.input[start..end];
+                Ok(match self.headers.iter().position(|h| h == word) {
+                    Some(idx) => Operand::Column(idx),
+                    None => Operand::Literal(word.to_string()),
+                })
+            }
+            _ => anyhow::bail!("Expected a column or value in expression '{}'", self.input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> StringRecord {
+        StringRecord::from(vec!["Name", "Nationality", "Kit Number"])
+    }
+
+    #[test]
+    fn test_parse_expr() -> anyhow::Result<()> {
+        let headers = headers();
+        assert_eq!(
+            parse_expr("Kit Number > 10", &headers)?,
+            Expr::Cmp(Operand::Column(2), CmpOp::Gt, Operand::Literal("10".into()))
+        );
+        assert_eq!(
+            parse_expr("Nationality == 'Costa Rica'", &headers)?,
+            Expr::Cmp(Operand::Column(1), CmpOp::Eq, Operand::Literal("Costa Rica".into()))
+        );
+        assert!(parse_expr("Nationalty == Italy", &headers).is_err());
+        assert!(parse_expr("Name ==", &headers).is_err());
+        assert!(parse_expr("(Name == a", &headers).is_err());
+        assert!(parse_expr("Name == 'a", &headers).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_expr() -> anyhow::Result<()> {
+        let headers = headers();
+        let record = StringRecord::from(vec!["Gianluigi Buffon", "Italy", "77"]);
+        let check = |expr: &str| parse_expr(expr, &headers).map(|e| e.eval(&record));
+        assert!(check("Nationality == Italy")?);
+        assert!(check("Kit Number > 9")?);
+        assert!(!check("Kit Number < 9")?);
+        assert!(check("Kit Number >= 77.0 && Name ~= Buffon")?);
+        assert!(check("Nationality != Italy or not (Kit Number <= 10)")?);
+        assert!(!check("!(Nationality = Italy)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline() -> anyhow::Result<()> {
+        let mut reader = csv::Reader::from_path("assets/juventus.csv")?;
+        let headers = reader.headers()?.clone();
+        let opts = CsvFilterOpts {
+            select: Some(vec!["Name".into(), "Kit Number".into()]),
+            filter: vec!["Nationality == Italy".into()],
+            sort: Some("Kit Number".into()),
+            desc: true,
+            limit: Some(2),
+        };
+        let pipeline = RecordPipeline::new(&headers, &opts)?;
+        assert_eq!(pipeline.headers(), &StringRecord::from(vec!["Name", "Kit Number"]));
+        let records = reader.into_records().map(|r| r.map_err(anyhow::Error::from));
+        let rows = pipeline.apply(records).collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                StringRecord::from(vec!["Gianluigi Buffon", "77"]),
+                StringRecord::from(vec!["Mattia Perin", "37"]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_values() {
+        assert_eq!(compare_values("9", "10"), Ordering::Less);
+        assert_eq!(compare_values("Jan 28, 1978 (41)", "Apr 18, 1990 (29)"), Ordering::Less);
+        assert_eq!(compare_values("b", "a"), Ordering::Greater);
+        // numbers before dates before text, whatever the text looks like
+        assert_eq!(compare_values("10", "1a"), Ordering::Less);
+        assert_eq!(compare_values("1a", "2"), Ordering::Greater);
+        assert_eq!(compare_values("1990-04-18", "1"), Ordering::Greater);
+        assert_eq!(compare_values("NaN", "1"), Ordering::Greater);
+        assert_eq!(compare_same_kind("9", "10"), Ok(Ordering::Less));
+        assert_eq!(compare_same_kind("x", "10"), Err("number"));
+        assert_eq!(compare_same_kind("1", "2020-01-02"), Err("date"));
+    }
+
+    #[test]
+    fn test_sort_mixed_column() -> anyhow::Result<()> {
+        let headers = StringRecord::from(vec!["v"]);
+        let opts = CsvFilterOpts { sort: Some("v".into()), ..Default::default() };
+        let pipeline = RecordPipeline::new(&headers, &opts)?;
+        let values = ["1a", "10", "b", "2", "2019-01-01", "2"];
+        let records = values.iter().map(|v| Ok(StringRecord::from(vec![*v])));
+        let rows = pipeline.apply(records).collect::<anyhow::Result<Vec<_>>>()?;
+        let sorted = rows.iter().map(|r| &r[0]).collect::<Vec<_>>();
+        assert_eq!(sorted, ["2", "2", "10", "2019-01-01", "1a", "b"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use csv::WriterBuilder;
+use serde_json::Value;
+
+use super::csv_nested::flatten;
+use crate::{InputFormat, get_reader, get_writer};
+
+/// Convert json/ndjson/yaml records to csv.
+/// The header is the union of all (flattened) keys in first seen order, so it is stable across runs
+pub fn process_csv_import(
+    input: &Path,
+    output: &Path,
+    from: InputFormat,
+    missing: &str,
+    delimiter: u8,
+) -> anyhow::Result<()> {
+    let records = read_records(get_reader(input)?, from)?;
+
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    let mut rows = Vec::with_capacity(records.len());
+    for (idx, record) in records.iter().enumerate() {
+        if !record.is_object() {
+            anyhow::bail!("Record {} is not an object", idx + 1);
+        }
+        let mut fields = Vec::new();
+        flatten("", record, &mut fields);
+        for (key, _) in &fields {
+            if seen.insert(key.clone()) {
+                headers.push(key.clone());
+            }
+        }
+        rows.push(fields.into_iter().collect::<HashMap<_, _>>());
+    }
+
+    let mut writer = WriterBuilder::new().delimiter(delimiter).from_writer(get_writer(output)?);
+    writer.write_record(&headers)?;
+    for row in rows {
+        writer.write_record(headers.iter().map(|h| row.get(h).map_or(missing, String::as_str)))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_records(reader: Box<dyn Read>, from: InputFormat) -> anyhow::Result<Vec<Value>> {
+    let records = match from {
+        InputFormat::Json => serde_json::from_reader(reader)?,
+        InputFormat::Yaml => serde_yaml::from_reader(reader)?,
+        InputFormat::Ndjson => {
+            let mut records = Vec::new();
+            for (idx, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line)
+                    .map_err(|e| anyhow::anyhow!("Invalid json on line {}: {}", idx + 1, e))?;
+                records.push(record);
+            }
+            Value::Array(records)
+        }
+    };
+    // a single object is a single record
+    match records {
+        Value::Array(records) => Ok(records),
+        Value::Object(_) => Ok(vec![records]),
+        _ => anyhow::bail!("Expected a sequence of records in the {} input", from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{OutputFormat, process_csv};
+
+    fn import(content: &str, from: InputFormat, missing: &str) -> anyhow::Result<String> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        let output = NamedTempFile::new()?;
+        process_csv_import(input.path(), output.path(), from, missing, b',')?;
+        Ok(std::fs::read_to_string(output.path())?)
+    }
+
+    #[test]
+    fn test_import_header_union_and_nesting() -> anyhow::Result<()> {
+        let content = r#"{"name": "Alice", "address": {"city": "Paris"}, "tags": ["a", "b"]}
+{"name": "Bob", "age": 30, "active": true}
+"#;
+        let ret = import(content, InputFormat::Ndjson, "N/A")?;
+        assert_eq!(
+            ret,
+            "name,address.city,tags[0],tags[1],age,active\nAlice,Paris,a,b,N/A,N/A\nBob,N/A,N/A,N/A,30,true\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_yaml() -> anyhow::Result<()> {
+        let ret = import("- name: Alice\n  age: 30\n- name: Bob\n", InputFormat::Yaml, "")?;
+        assert_eq!(ret, "name,age\nAlice,30\nBob,\n");
+        assert!(import("name: Alice", InputFormat::Yaml, "").is_ok());
+        assert!(import("[1, 2]", InputFormat::Json, "").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let input = Path::new("assets/juventus.csv");
+        for (format, from) in [
+            (OutputFormat::Json, InputFormat::Json),
+            (OutputFormat::Ndjson, InputFormat::Ndjson),
+            (OutputFormat::Yaml, InputFormat::Yaml),
+        ] {
+            let converted = NamedTempFile::new()?;
+            process_csv(
+                input,
+                converted.path(),
+                format,
+                false,
+                &Default::default(),
+                &mut Default::default(),
+            )?;
+            let output = NamedTempFile::new()?;
+            process_csv_import(converted.path(), output.path(), from, "", b',')?;
+            assert_eq!(std::fs::read_to_string(output.path())?, std::fs::read_to_string(input)?);
+        }
+        Ok(())
+    }
+}
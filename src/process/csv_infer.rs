@@ -0,0 +1,292 @@
+use chrono::NaiveDate;
+use csv::StringRecord;
+use serde_json::{Map, Number, Value};
+
+use crate::{CsvColumnType, CsvTypeOpts};
+
+/// Number of leading records used to infer column types
+const INFER_SAMPLE_SIZE: usize = 1024;
+
+/// Formats accepted when a column is explicitly typed as `date`
+const DATE_FORMATS: [&str; 6] =
+    ["%Y-%m-%d", "%Y/%m/%d", "%b %d, %Y", "%B %d, %Y", "%d %b %Y", "%d %B %Y"];
+
+/// Collects the narrowest type able to hold every non-empty value of each column
+#[derive(Debug, Default)]
+pub struct TypeInference {
+    types: Vec<Option<CsvColumnType>>,
+}
+
+impl TypeInference {
+    pub fn observe(&mut self, record: &StringRecord) {
+        if self.types.len() < record.len() {
+            self.types.resize(record.len(), None);
+        }
+        for (ty, value) in self.types.iter_mut().zip(record.iter()) {
+            if let Some(value_ty) = classify(value) {
+                *ty = Some(match *ty {
+                    Some(ty) => widen(ty, value_ty),
+                    None => value_ty,
+                });
+            }
+        }
+    }
+
+    /// columns that only held empty values are treated as strings
+    pub fn finish(self, width: usize) -> Vec<CsvColumnType> {
+        (0..width)
+            .map(|i| self.types.get(i).copied().flatten().unwrap_or(CsvColumnType::String))
+            .collect()
+    }
+}
+
+/// Turns string records into json objects using the resolved type of every column
+#[derive(Debug)]
+pub struct RecordConverter {
+    headers: StringRecord,
+    types: Vec<CsvColumnType>,
+    /// columns typed by `--type`, their values must convert or the record is rejected
+    explicit: Vec<bool>,
+    infer: bool,
+    /// keep a late value that does not fit its inferred type as a string instead of failing
+    lenient: bool,
+}
+
+impl RecordConverter {
+    /// Resolve the final type of every column from the inferred types and `--type` overrides
+    pub fn new(
+        headers: StringRecord,
+        inferred: TypeInference,
+        opts: &CsvTypeOpts,
+    ) -> anyhow::Result<Self> {
+        let mut types = if opts.should_infer() {
+            inferred.finish(headers.len())
+        } else {
+            vec![CsvColumnType::String; headers.len()]
+        };
+        let mut explicit = vec![false; headers.len()];
+        for o in &opts.types {
+            let Some(idx) = headers.iter().position(|h| h == o.column) else {
+                anyhow::bail!("Column '{}' in --type does not exist", o.column);
+            };
+            types[idx] = o.ty;
+            explicit[idx] = true;
+        }
+        Ok(Self { headers, types, explicit, infer: opts.should_infer(), lenient: false })
+    }
+
+    /// For targets that type every value on its own, like sqlite, where a string among
+    /// numbers is not an error
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    pub fn types(&self) -> &[CsvColumnType] {
+        &self.types
+    }
+
+    /// `idx` is the 0-based record index, used for error reporting.
+    /// Inferred types come from a sample, so a later float in an integer column is still
+    /// written as a number, while any other value that does not fit is an error
+    pub fn convert(&self, record: &StringRecord, idx: usize) -> anyhow::Result<Value> {
+        let mut ret = Map::with_capacity(self.headers.len());
+        for (i, (name, value)) in self.headers.iter().zip(record.iter()).enumerate() {
+            let ty = self.types[i];
+            let value = match convert_value(value, ty, self.infer) {
+                Some(value) => value,
+                None if self.explicit[i] => anyhow::bail!(
+                    "Cannot convert '{}' in column '{}' of record {} to {}",
+                    value,
+                    name,
+                    idx + 1,
+                    ty
+                ),
+                None => match widen_late(value, ty, self.infer) {
+                    Some(value) => value,
+                    None if self.lenient => Value::String(value.to_string()),
+                    None => anyhow::bail!(
+                        "Value '{}' in column '{}' of record {} does not fit the type {} inferred from the first {} records, use --type \"{}=string\" to keep the column as text",
+                        value,
+                        name,
+                        idx + 1,
+                        ty,
+                        INFER_SAMPLE_SIZE,
+                        name
+                    ),
+                },
+            };
+            ret.insert(name.to_string(), value);
+        }
+        Ok(Value::Object(ret))
+    }
+}
+
+/// Infer column types from the first records and build their converter.
+/// The records are handed back whole, the sample followed by the rest
+pub fn infer_converter<I>(
+    mut records: I,
+    headers: &StringRecord,
+    type_opts: &CsvTypeOpts,
+) -> anyhow::Result<(RecordConverter, impl Iterator<Item = anyhow::Result<StringRecord>>)>
+where
+    I: Iterator<Item = anyhow::Result<StringRecord>>,
+{
+    let sample = records.by_ref().take(INFER_SAMPLE_SIZE).collect::<anyhow::Result<Vec<_>>>()?;
+    let mut inference = TypeInference::default();
+    sample.iter().for_each(|record| inference.observe(record));
+    let converter = RecordConverter::new(headers.clone(), inference, type_opts)?;
+    Ok((converter, sample.into_iter().map(Ok).chain(records)))
+}
+
+/// A value the sample did not foresee, converted to a wider type all earlier values also fit
+fn widen_late(value: &str, ty: CsvColumnType, infer: bool) -> Option<Value> {
+    match (ty, classify(value)) {
+        (CsvColumnType::Int, Some(CsvColumnType::Float)) => {
+            convert_value(value, CsvColumnType::Float, infer)
+        }
+        _ => None,
+    }
+}
+
+/// The type of a single value, `None` for an empty one
+pub fn classify(value: &str) -> Option<CsvColumnType> {
+    if value.is_empty() {
+        None
+    } else if is_integer(value) {
+        Some(CsvColumnType::Int)
+    } else if is_float(value) {
+        Some(CsvColumnType::Float)
+    } else if parse_bool(value).is_some() {
+        Some(CsvColumnType::Bool)
+    } else if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+        Some(CsvColumnType::Date)
+    } else {
+        Some(CsvColumnType::String)
+    }
+}
+
+/// Convert a field to a json value of the given type, `None` when it does not fit.
+/// `infer` decides whether an empty field becomes null (typed columns always map it to null)
+pub fn convert_value(value: &str, ty: CsvColumnType, infer: bool) -> Option<Value> {
+    if value.is_empty() && (infer || ty != CsvColumnType::String) {
+        return Some(Value::Null);
+    }
+    match ty {
+        CsvColumnType::Int => value.parse::<i64>().ok().map(Value::from),
+        CsvColumnType::Float => {
+            value.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number)
+        }
+        CsvColumnType::Bool => parse_bool(value).map(Value::Bool),
+        CsvColumnType::Date => parse_date(value).map(|d| Value::String(d.to_string())),
+        CsvColumnType::String => Some(Value::String(value.to_string())),
+    }
+}
+
+/// Parse ISO and a few common human formats such as `Jan 19, 1978`,
+/// ignoring a trailing parenthesized remark like `Apr 18, 1990 (29)`
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    DATE_FORMATS.iter().find_map(|fmt| {
+        let (date, rest) = NaiveDate::parse_and_remainder(value, fmt).ok()?;
+        let rest = rest.trim();
+        (rest.is_empty() || (rest.starts_with('(') && rest.ends_with(')'))).then_some(date)
+    })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn is_integer(value: &str) -> bool {
+    !value.starts_with('+') && !has_leading_zero(value) && value.parse::<i64>().is_ok()
+}
+
+// reject `inf`, `NaN` and friends which rust happily parses as floats
+fn is_float(value: &str) -> bool {
+    value.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+        && value.bytes().any(|b| b.is_ascii_digit())
+        && !has_leading_zero(value)
+        && value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+// leading zeros (zip codes, ids) are kept as strings so no information is lost
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.trim_start_matches(['-', '+']).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+fn widen(a: CsvColumnType, b: CsvColumnType) -> CsvColumnType {
+    use CsvColumnType::*;
+    match (a, b) {
+        _ if a == b => a,
+        (Int, Float) | (Float, Int) => Float,
+        _ => String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(""), None);
+        assert_eq!(classify("10"), Some(CsvColumnType::Int));
+        assert_eq!(classify("-3"), Some(CsvColumnType::Int));
+        assert_eq!(classify("007"), Some(CsvColumnType::String));
+        assert_eq!(classify("1.5e3"), Some(CsvColumnType::Float));
+        assert_eq!(classify("NaN"), Some(CsvColumnType::String));
+        assert_eq!(classify("True"), Some(CsvColumnType::Bool));
+        assert_eq!(classify("1978-01-19"), Some(CsvColumnType::Date));
+        assert_eq!(classify("Jan 19, 1978"), Some(CsvColumnType::String));
+    }
+
+    #[test]
+    fn test_type_inference() {
+        let mut inference = TypeInference::default();
+        inference.observe(&StringRecord::from(vec!["1", "1", "", "x"]));
+        inference.observe(&StringRecord::from(vec!["2", "2.5", "", "true"]));
+        assert_eq!(
+            inference.finish(4),
+            vec![
+                CsvColumnType::Int,
+                CsvColumnType::Float,
+                CsvColumnType::String,
+                CsvColumnType::String
+            ]
+        );
+    }
+
+    #[test]
+    fn test_late_values() -> anyhow::Result<()> {
+        let headers = StringRecord::from(vec!["n", "flag"]);
+        let mut inference = TypeInference::default();
+        inference.observe(&StringRecord::from(vec!["1", "true"]));
+        let converter = RecordConverter::new(headers.clone(), inference, &Default::default())?;
+        let value = converter.convert(&StringRecord::from(vec!["1.5", ""]), 1)?;
+        assert_eq!(value, serde_json::json!({"n": 1.5, "flag": null}));
+        let err = converter.convert(&StringRecord::from(vec!["N/A", "true"]), 2).unwrap_err();
+        assert!(err.to_string().contains("use --type \"n=string\""));
+
+        let mut inference = TypeInference::default();
+        inference.observe(&StringRecord::from(vec!["1", "true"]));
+        let converter = RecordConverter::new(headers, inference, &Default::default())?.lenient();
+        let value = converter.convert(&StringRecord::from(vec!["N/A", "yes"]), 2)?;
+        assert_eq!(value, serde_json::json!({"n": "N/A", "flag": "yes"}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let expected = NaiveDate::from_ymd_opt(1990, 4, 18);
+        assert_eq!(parse_date("1990-04-18"), expected);
+        assert_eq!(parse_date("Apr 18, 1990 (29)"), expected);
+        assert_eq!(parse_date("April 18, 1990"), expected);
+        assert_eq!(parse_date("Apr 18, 1990 and more"), None);
+    }
+}
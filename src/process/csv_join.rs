@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use csv::StringRecord;
+
+use super::csv_reader::{
+    Rejects, build_reader, build_writer, key_indices, read_headers, read_records, writes_headers,
+};
+use crate::{CsvReaderOpts, JoinHow};
+
+/// Join `left` and `right` on the `on` columns and write the result to `output`.
+/// The right file is held in memory, the left one is streamed
+pub fn process_csv_join(
+    left: &Path,
+    right: &Path,
+    output: &Path,
+    on: &[String],
+    how: JoinHow,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    let mut right_reader = build_reader(right, opts)?;
+    let right_headers = read_headers(&mut right_reader, opts)?;
+    let right_keys = key_indices(&right_headers, on, right)?;
+    let mut right_rows: Vec<(StringRecord, bool)> = Vec::new();
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for record in read_records(right_reader, right, opts, rejects)? {
+        let record = record?;
+        index.entry(key_of(&record, &right_keys)).or_default().push(right_rows.len());
+        right_rows.push((record, false));
+    }
+
+    let mut left_reader = build_reader(left, opts)?;
+    let left_headers = read_headers(&mut left_reader, opts)?;
+    let left_keys = key_indices(&left_headers, on, left)?;
+
+    // key columns appear once, other right columns clashing with a left one get a `_right` suffix
+    let right_columns =
+        (0..right_headers.len()).filter(|i| !right_keys.contains(i)).collect::<Vec<_>>();
+    let taken = left_headers.iter().collect::<HashSet<_>>();
+    let mut headers = left_headers.clone();
+    for &i in &right_columns {
+        let name = &right_headers[i];
+        if taken.contains(name) {
+            headers.push_field(&format!("{}_right", name));
+        } else {
+            headers.push_field(name);
+        }
+    }
+
+    let mut writer = build_writer(output, opts)?;
+    if writes_headers(opts) {
+        writer.write_record(&headers)?;
+    }
+    for record in read_records(left_reader, left, opts, rejects)? {
+        let record = record?;
+        let matches = index.get(&key_of(&record, &left_keys));
+        match matches {
+            Some(matches) => {
+                for &m in matches {
+                    right_rows[m].1 = true;
+                    let right = &right_rows[m].0;
+                    writer.write_record(
+                        record.iter().chain(right_cells(&right_columns, Some(right))),
+                    )?;
+                }
+            }
+            None if how != JoinHow::Inner => {
+                writer.write_record(record.iter().chain(right_cells(&right_columns, None)))?;
+            }
+            None => {}
+        }
+    }
+    if how == JoinHow::Outer {
+        for (record, _) in right_rows.iter().filter(|(_, matched)| !matched) {
+            // an unmatched right row still fills the key columns on the left side
+            let left_cells = (0..left_headers.len()).map(|i| {
+                left_keys
+                    .iter()
+                    .position(|&k| k == i)
+                    .and_then(|k| record.get(right_keys[k]))
+                    .unwrap_or_default()
+            });
+            writer.write_record(left_cells.chain(right_cells(&right_columns, Some(record))))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The non-key cells of a right row, empty when there is no match
+fn right_cells<'a>(
+    columns: &'a [usize],
+    record: Option<&'a StringRecord>,
+) -> impl Iterator<Item = &'a str> + 'a {
+    columns.iter().map(move |&i| record.and_then(|r| r.get(i)).unwrap_or_default())
+}
+
+fn key_of(record: &StringRecord, keys: &[usize]) -> Vec<String> {
+    keys.iter().map(|&i| record.get(i).unwrap_or_default().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn join(left: &str, right: &str, how: JoinHow) -> anyhow::Result<String> {
+        let mut left_file = NamedTempFile::new()?;
+        left_file.write_all(left.as_bytes())?;
+        let mut right_file = NamedTempFile::new()?;
+        right_file.write_all(right.as_bytes())?;
+        let output = NamedTempFile::new()?;
+        process_csv_join(
+            left_file.path(),
+            right_file.path(),
+            output.path(),
+            &["id".to_string()],
+            how,
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        Ok(std::fs::read_to_string(output.path())?)
+    }
+
+    const LEFT: &str = "id,name\n1,Alice\n2,Bob\n";
+    const RIGHT: &str = "name,id\nParis,1\nRome,3\nLyon,1\n";
+
+    #[test]
+    fn test_join_kinds() -> anyhow::Result<()> {
+        assert_eq!(
+            join(LEFT, RIGHT, JoinHow::Inner)?,
+            "id,name,name_right\n1,Alice,Paris\n1,Alice,Lyon\n"
+        );
+        assert_eq!(
+            join(LEFT, RIGHT, JoinHow::Left)?,
+            "id,name,name_right\n1,Alice,Paris\n1,Alice,Lyon\n2,Bob,\n"
+        );
+        assert_eq!(
+            join(LEFT, RIGHT, JoinHow::Outer)?,
+            "id,name,name_right\n1,Alice,Paris\n1,Alice,Lyon\n2,Bob,\n3,,Rome\n"
+        );
+        assert!(join("key,name\n1,a\n", RIGHT, JoinHow::Inner).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+/// Largest `[index]` accepted in a column name, every record allocates the array up to it
+const MAX_INDEX: usize = 9999;
+
+/// One step of a column path: `address.city` is two keys, `tags[0]` a key and an index
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Flatten nested objects to `a.b` keys and arrays to `a[0]` keys
+pub fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key =
+                    if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&key, value, fields);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (idx, value) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, idx), value, fields);
+            }
+        }
+        // an empty record has no columns rather than one named ""
+        _ if prefix.is_empty() => {}
+        Value::Object(_) | Value::Array(_) | Value::Null => {
+            fields.push((prefix.to_string(), String::new()));
+        }
+        Value::String(s) => fields.push((prefix.to_string(), s.clone())),
+        Value::Bool(_) | Value::Number(_) => fields.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+/// Build nested objects and arrays from a flat record whose keys are `a.b` or `a[0]` paths
+pub fn unflatten(record: &Value) -> anyhow::Result<Value> {
+    let Value::Object(fields) = record else {
+        return Ok(record.clone());
+    };
+    let mut ret = Value::Object(Map::new());
+    for (key, value) in fields {
+        insert(&mut ret, key, &parse_path(key), value.clone())?;
+    }
+    Ok(ret)
+}
+
+/// The top level keys left after unflattening, in first seen order
+pub fn unflatten_headers(headers: &StringRecord) -> StringRecord {
+    let mut seen = HashSet::new();
+    headers
+        .iter()
+        .map(|h| match parse_path(h).first() {
+            Some(Segment::Key(key)) => *key,
+            _ => h,
+        })
+        .filter(|key| seen.insert(*key))
+        .collect()
+}
+
+fn insert(target: &mut Value, key: &str, path: &[Segment], value: Value) -> anyhow::Result<()> {
+    let Some((segment, rest)) = path.split_first() else {
+        if !target.is_null() {
+            anyhow::bail!("Column '{}' conflicts with another column", key);
+        }
+        *target = value;
+        return Ok(());
+    };
+    match segment {
+        Segment::Key(k) => {
+            if target.is_null() {
+                *target = Value::Object(Map::new());
+            }
+            let Value::Object(map) = target else {
+                anyhow::bail!("Column '{}' conflicts with another column", key);
+            };
+            let child = map.entry(k.to_string()).or_insert(Value::Null);
+            insert(child, key, rest, value)
+        }
+        Segment::Index(idx) => {
+            if target.is_null() {
+                *target = Value::Array(Vec::new());
+            }
+            let Value::Array(items) = target else {
+                anyhow::bail!("Column '{}' conflicts with another column", key);
+            };
+            if *idx > MAX_INDEX {
+                anyhow::bail!("Index {} in column '{}' is larger than {}", idx, key, MAX_INDEX);
+            }
+            let Some(len) = idx.checked_add(1) else {
+                anyhow::bail!("Index {} in column '{}' is too large", idx, key);
+            };
+            if items.len() < len {
+                items.resize(len, Value::Null);
+            }
+            insert(&mut items[*idx], key, rest, value)
+        }
+    }
+}
+
+/// Split `a.b[0][1]` into segments, text that is not a valid `[index]` stays part of the key.
+/// A name with an empty part, like `Kit No.` or `a..b`, is a plain key
+fn parse_path(key: &str) -> Vec<Segment<'_>> {
+    if key.split('.').any(str::is_empty) {
+        return vec![Segment::Key(key)];
+    }
+    let mut ret = Vec::new();
+    for part in key.split('.') {
+        let mut name_end = part.len();
+        let mut indices = Vec::new();
+        let mut rest = part;
+        while let Some(inner) = rest.strip_suffix(']') {
+            let Some((head, idx)) = inner.rsplit_once('[') else {
+                break;
+            };
+            let Ok(idx) = idx.parse::<usize>() else {
+                break;
+            };
+            indices.push(Segment::Index(idx));
+            rest = head;
+            name_end = head.len();
+        }
+        if name_end > 0 {
+            ret.push(Segment::Key(&part[..name_end]));
+        }
+        ret.extend(indices.into_iter().rev());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("name"), vec![Segment::Key("name")]);
+        assert_eq!(
+            parse_path("a.b[0][2]"),
+            vec![Segment::Key("a"), Segment::Key("b"), Segment::Index(0), Segment::Index(2)]
+        );
+        assert_eq!(parse_path("x[y]"), vec![Segment::Key("x[y]")]);
+        assert_eq!(parse_path("Kit No."), vec![Segment::Key("Kit No.")]);
+        assert_eq!(parse_path(".a"), vec![Segment::Key(".a")]);
+    }
+
+    #[test]
+    fn test_unflatten() -> anyhow::Result<()> {
+        let record = json!({
+            "id": 1,
+            "address.city": "Turin",
+            "address.zip": "10121",
+            "tags[0]": "a",
+            "tags[2]": "c",
+        });
+        assert_eq!(
+            unflatten(&record)?,
+            json!({
+                "id": 1,
+                "address": {"city": "Turin", "zip": "10121"},
+                "tags": ["a", null, "c"],
+            })
+        );
+        assert!(unflatten(&json!({"a": 1, "a.b": 2})).is_err());
+        assert!(unflatten(&json!({"a.b": 1, "a[0]": 2})).is_err());
+        let err = unflatten(&json!({"tags[18446744073709551615]": 1})).unwrap_err();
+        assert!(err.to_string().contains("'tags[18446744073709551615]'"));
+        assert!(unflatten(&json!({"tags[99999999999]": 1})).is_err());
+        assert_eq!(unflatten(&json!({"Kit No.": 7}))?, json!({"Kit No.": 7}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_round_trip() -> anyhow::Result<()> {
+        let record = json!({"a": {"b": [1, {"c": "x"}]}, "d": true});
+        let mut fields = Vec::new();
+        flatten("", &record, &mut fields);
+        assert_eq!(
+            fields,
+            vec![
+                ("a.b[0]".to_string(), "1".to_string()),
+                ("a.b[1].c".to_string(), "x".to_string()),
+                ("d".to_string(), "true".to_string()),
+            ]
+        );
+        let headers = fields.iter().map(|(k, _)| k.as_str()).collect::<StringRecord>();
+        assert_eq!(unflatten_headers(&headers), StringRecord::from(vec!["a", "d"]));
+
+        let mut fields = Vec::new();
+        flatten("", &json!({}), &mut fields);
+        assert!(fields.is_empty());
+        flatten("", &json!({"a": {}, "b": []}), &mut fields);
+        assert_eq!(
+            fields,
+            vec![("a".to_string(), String::new()), ("b".to_string(), String::new())]
+        );
+        Ok(())
+    }
+}
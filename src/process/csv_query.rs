@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+use csv::StringRecord;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{Connection, params_from_iter};
+use serde_json::{Map, Value};
+
+use super::csv_infer::infer_converter;
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use super::csv_show::{TableStyle, render_table};
+use super::csv_writer::{cell_text, record_writer};
+use crate::{CsvProcessOpts, CsvQueryTable, CsvReaderOpts, OutputFormat, get_writer};
+
+/// Run a sql query over csv files, every input is loaded into an in-memory sqlite table.
+/// The result is written as a table when `format` is `None`
+pub fn process_csv_query(
+    query: &str,
+    tables: &[CsvQueryTable],
+    output: &Path,
+    opts: &CsvProcessOpts,
+    rejects: &mut Rejects,
+    format: Option<OutputFormat>,
+    style: TableStyle,
+) -> anyhow::Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    for table in tables {
+        load_table(&mut conn, table, &opts.reader, rejects)?;
+    }
+    let mut stmt = conn.prepare(query).map_err(|e| missing_table_hint(e, tables))?;
+    let headers = unique_names(stmt.column_names());
+    let mut rows = stmt.query([])?;
+    let mut next_record = || -> anyhow::Result<Option<Map<String, Value>>> {
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let mut record = Map::new();
+        for (i, name) in headers.iter().enumerate() {
+            record.insert(name.to_string(), json_value(row.get_ref(i)?));
+        }
+        Ok(Some(record))
+    };
+
+    let mut writer = get_writer(output)?;
+    match format {
+        None => {
+            let mut body = Vec::new();
+            while let Some(record) = next_record()? {
+                body.push(record.values().map(cell_text).collect::<StringRecord>());
+            }
+            writer.write_all(render_table(&headers, &body, 60, style).as_bytes())?;
+            writer.flush()?;
+            Ok(())
+        }
+        Some(format) => {
+            // sqlite types every value on its own, parquet and arrow types come from the first row group
+            let mut writer = record_writer(format, writer, &headers, None, &opts.columnar);
+            while let Some(record) = next_record()? {
+                writer.write_record(&Value::Object(record))?;
+            }
+            writer.finish()
+        }
+    }
+}
+
+/// Create a table named after `table` holding the typed records of its csv file
+fn load_table(
+    conn: &mut Connection,
+    table: &CsvQueryTable,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    let mut reader = build_reader(&table.path, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let records = read_records(reader, &table.path, opts, rejects)?;
+    let (converter, records) = infer_converter(records, &headers, &Default::default())?;
+    // sqlite types every value on its own, so a late string among numbers is kept as is
+    let converter = converter.lenient();
+
+    // columns are declared without a type, sqlite then keeps every value as it was bound
+    let columns = headers.iter().map(quote_identifier).collect::<Vec<_>>();
+    let placeholders = vec!["?"; columns.len()];
+    let name = quote_identifier(&table.name);
+    let tx = conn.transaction()?;
+    tx.execute(&format!("CREATE TABLE {} ({})", name, columns.join(", ")), [])
+        .map_err(|e| anyhow::anyhow!("Cannot create table {}: {}", name, e))?;
+    {
+        let mut insert =
+            tx.prepare(&format!("INSERT INTO {} VALUES ({})", name, placeholders.join(", ")))?;
+        for (idx, record) in records.enumerate() {
+            let value = converter.convert(&record?, idx)?;
+            let values = value.as_object().into_iter().flat_map(|map| map.values());
+            insert.execute(params_from_iter(values.map(sql_value)))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// A table the query names but no input registers is most likely a file named after its stem
+fn missing_table_hint(error: rusqlite::Error, tables: &[CsvQueryTable]) -> anyhow::Error {
+    let message = error.to_string();
+    match message.strip_prefix("no such table: ") {
+        Some(name) => {
+            let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+            anyhow::anyhow!(
+                "{}, the inputs are registered as {}, use -i {}=FILE to name a table",
+                message,
+                names.join(", "),
+                name
+            )
+        }
+        None => error.into(),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        _ => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+        ValueRef::Blob(b) => String::from_utf8_lossy(b).into(),
+    }
+}
+
+/// Result columns of a join may share a name, later ones get a `_2`, `_3`... suffix
+fn unique_names(names: Vec<&str>) -> StringRecord {
+    let mut taken = HashSet::new();
+    let mut ret = StringRecord::with_capacity(0, names.len());
+    for name in names {
+        // `id,id,id_2` must not give `id_2` twice, so keep counting until the name is free
+        let mut unique = name.to_string();
+        let mut count = 1;
+        while !taken.insert(unique.clone()) {
+            count += 1;
+            unique = format!("{}_{}", name, count);
+        }
+        ret.push_field(&unique);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn query(sql: &str, tables: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let tables = tables
+            .iter()
+            .map(|(name, path)| CsvQueryTable { name: name.to_string(), path: PathBuf::from(path) })
+            .collect::<Vec<_>>();
+        let output = NamedTempFile::new()?;
+        process_csv_query(
+            sql,
+            &tables,
+            output.path(),
+            &Default::default(),
+            &mut Default::default(),
+            Some(OutputFormat::Json),
+            TableStyle::Plain,
+        )?;
+        Ok(serde_json::from_str(&std::fs::read_to_string(output.path())?)?)
+    }
+
+    #[test]
+    fn test_query_group_by() -> anyhow::Result<()> {
+        let sql = "SELECT Position, count(*) AS players, max(\"Kit Number\") AS top_kit FROM players
+                   WHERE Nationality != 'Brazil' GROUP BY Position ORDER BY players DESC, Position LIMIT 2";
+        let ret = query(sql, &[("players", "assets/juventus.csv")])?;
+        assert_eq!(
+            ret,
+            serde_json::json!([
+                {"Position": "Central Midfield", "players": 6, "top_kit": 30},
+                {"Position": "Centre-Back", "players": 5, "top_kit": 28},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_join() -> anyhow::Result<()> {
+        let mut nations = NamedTempFile::new()?;
+        nations.write_all(b"Nationality,Continent\nItaly,Europe\nBrazil,South America\n")?;
+        let sql = "SELECT p.Name, n.Continent FROM players p JOIN nations n USING (Nationality)
+                   WHERE n.Continent = 'South America' ORDER BY p.Name LIMIT 1";
+        let path = nations.path().to_str().unwrap();
+        let ret = query(sql, &[("players", "assets/juventus.csv"), ("nations", path)])?;
+        assert_eq!(ret, serde_json::json!([{"Name": "Alex Sandro", "Continent": "South America"}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_errors_and_names() -> anyhow::Result<()> {
+        let err =
+            query("SELECT * FROM missing", &[("juventus", "assets/juventus.csv")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no such table: missing, the inputs are registered as juventus, use -i missing=FILE to name a table"
+        );
+        assert_eq!(
+            unique_names(vec!["id", "name", "id"]),
+            StringRecord::from(vec!["id", "name", "id_2"])
+        );
+        assert_eq!(
+            unique_names(vec!["id", "id", "id_2"]),
+            StringRecord::from(vec!["id", "id_2", "id_2_2"])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use csv::{ByteRecord, Reader, ReaderBuilder, StringRecord, Trim, Writer, WriterBuilder};
+
+use super::csv_encoding::decode_reader;
+use super::csv_error::{CsvError, display_path};
+use super::csv_xlsx::{is_spreadsheet, read_sheet};
+use crate::{CsvReaderOpts, CsvTrim, get_reader, get_writer};
+
+/// Records of a csv input with located errors, see `read_records`
+pub struct Records<'r> {
+    reader: Reader<Box<dyn Read>>,
+    path: String,
+    has_headers: bool,
+    /// names of the header row, to name the field of an error
+    names: Option<ByteRecord>,
+    width: usize,
+    /// the rejects file of this input, with `--skip-bad-rows`
+    rejects: Option<(PathBuf, &'r mut Rejects)>,
+    record: ByteRecord,
+    done: bool,
+}
+
+/// Rows skipped by `--skip-bad-rows`. A command owns one for all of its inputs, so inputs
+/// sharing a rejects file append to it, and calls `finish` once it is done
+#[derive(Default)]
+pub struct Rejects {
+    files: Vec<RejectsFile>,
+}
+
+/// A rejects file, created on the first record rejected into it
+struct RejectsFile {
+    path: PathBuf,
+    writer: Writer<File>,
+    count: usize,
+}
+
+/// Build a csv reader over a file or stdin (`-`) honoring the delimiter/quote/escape/comment/trim options.
+/// Input in other encodings is transcoded to utf-8 before parsing, spreadsheets are read by sheet
+pub fn build_reader(input: &Path, opts: &CsvReaderOpts) -> anyhow::Result<Reader<Box<dyn Read>>> {
+    let trim = match opts.trim {
+        CsvTrim::None => Trim::None,
+        CsvTrim::Headers => Trim::Headers,
+        CsvTrim::Fields => Trim::Fields,
+        CsvTrim::All => Trim::All,
+    };
+    let reader = ReaderBuilder::new()
+        .has_headers(opts.has_headers())
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .escape(opts.escape)
+        .double_quote(opts.escape.is_none())
+        .comment(opts.comment)
+        .trim(trim)
+        // field counts are checked by `Records`, so bad rows can be skipped
+        .flexible(true)
+        .from_reader(source(input, opts)?);
+    Ok(reader)
+}
+
+/// The bytes to parse: a spreadsheet rendered as csv, or the input transcoded to utf-8
+fn source(input: &Path, opts: &CsvReaderOpts) -> anyhow::Result<Box<dyn Read>> {
+    if is_spreadsheet(input) {
+        return Ok(Box::new(Cursor::new(read_sheet(input, opts)?)));
+    }
+    if opts.sheet.is_some() {
+        anyhow::bail!("--sheet only applies to xlsx, xls and ods inputs");
+    }
+    decode_reader(get_reader(input)?, opts.encoding, opts.has_headers(), opts.quote)
+}
+
+/// Column names for the records of `reader`.
+/// Names given by `--columns` win, then the header row, then synthesized `col0, col1, ...`
+pub fn read_headers<R: Read>(
+    reader: &mut Reader<R>,
+    opts: &CsvReaderOpts,
+) -> anyhow::Result<StringRecord> {
+    // without a header row, `headers()` peeks the first record (it is still yielded by `records()`)
+    let first = reader.headers()?.clone();
+    let columns = opts.columns.as_deref().unwrap_or_default();
+    let width = first.len().max(columns.len());
+    let headers = (0..width)
+        .map(|i| match (columns.get(i), first.get(i)) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) if opts.has_headers() => name.to_string(),
+            _ => format!("col{}", i),
+        })
+        .collect();
+    Ok(headers)
+}
+
+/// Iterate the records of `reader`. Malformed records fail with a `CsvError`,
+/// or are written to the rejects file and skipped with `--skip-bad-rows`
+pub fn read_records<'r>(
+    mut reader: Reader<Box<dyn Read>>,
+    input: &Path,
+    opts: &CsvReaderOpts,
+    rejects: &'r mut Rejects,
+) -> anyhow::Result<Records<'r>> {
+    let path = display_path(input);
+    let headers =
+        reader.byte_headers().map_err(|error| CsvError::Read { path: path.clone(), error })?;
+    let width = headers.len();
+    let names = opts.has_headers().then(|| headers.clone());
+    let rejects = opts.skip_bad_rows.then(|| (opts.rejects_path(input), rejects));
+    Ok(Records {
+        reader,
+        path,
+        has_headers: opts.has_headers(),
+        names,
+        width,
+        rejects,
+        record: ByteRecord::new(),
+        done: false,
+    })
+}
+
+impl Records<'_> {
+    fn next_record(&mut self) -> Result<Option<StringRecord>, CsvError> {
+        loop {
+            let more = self
+                .reader
+                .read_byte_record(&mut self.record)
+                .map_err(|error| CsvError::Read { path: self.path.clone(), error })?;
+            if !more {
+                return Ok(None);
+            }
+            let error = match self.check() {
+                Ok(record) => return Ok(Some(record)),
+                Err(error) => error,
+            };
+            match self.rejects.as_mut() {
+                Some((path, rejects)) => rejects
+                    .write(path, self.names.as_ref(), &self.record, &error)
+                    .map_err(|e| CsvError::Write { path: path.display().to_string(), error: e })?,
+                None => return Err(error),
+            }
+        }
+    }
+
+    fn check(&self) -> Result<StringRecord, CsvError> {
+        let (line, record) = match self.record.position() {
+            Some(pos) => (pos.line(), pos.record() + u64::from(!self.has_headers)),
+            None => (0, 0),
+        };
+        if self.record.len() != self.width {
+            return Err(CsvError::UnequalLengths {
+                path: self.path.clone(),
+                line,
+                record,
+                expected: self.width,
+                found: self.record.len(),
+            });
+        }
+        StringRecord::from_byte_record(self.record.clone()).map_err(|e| {
+            let field = e.utf8_error().field();
+            let name = self.names.as_ref().and_then(|h| h.get(field));
+            CsvError::InvalidUtf8 {
+                path: self.path.clone(),
+                line,
+                record,
+                field: field + 1,
+                name: name.map(|n| String::from_utf8_lossy(n).to_string()).unwrap_or_default(),
+                valid_up_to: e.utf8_error().valid_up_to(),
+            }
+        })
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = anyhow::Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error.into()))
+            }
+        }
+    }
+}
+
+impl Rejects {
+    /// Write a rejected record to `path`, its raw fields prefixed by the line and the reason.
+    /// The header row comes from the first input writing to the file
+    fn write(
+        &mut self,
+        path: &Path,
+        headers: Option<&ByteRecord>,
+        record: &ByteRecord,
+        error: &CsvError,
+    ) -> csv::Result<()> {
+        let idx = match self.files.iter().position(|f| f.path == path) {
+            Some(idx) => idx,
+            None => {
+                let mut writer =
+                    WriterBuilder::new().flexible(true).from_writer(File::create(path)?);
+                let mut header = ByteRecord::from(vec!["_line", "_error"]);
+                if let Some(headers) = headers {
+                    header.extend(headers);
+                }
+                writer.write_byte_record(&header)?;
+                self.files.push(RejectsFile { path: path.to_path_buf(), writer, count: 0 });
+                self.files.len() - 1
+            }
+        };
+        let file = &mut self.files[idx];
+        let line = record.position().map_or(0, |p| p.line()).to_string();
+        let mut row = ByteRecord::from(vec![line, error.to_string()]);
+        row.extend(record);
+        file.writer.write_byte_record(&row)?;
+        file.count += 1;
+        Ok(())
+    }
+
+    /// Flush the rejects files, returning each path with its number of rejected records
+    pub fn finish(self) -> anyhow::Result<Vec<(PathBuf, usize)>> {
+        let mut ret = Vec::with_capacity(self.files.len());
+        for mut file in self.files {
+            file.writer.flush().map_err(|e| CsvError::Write {
+                path: file.path.display().to_string(),
+                error: e.into(),
+            })?;
+            ret.push((file.path, file.count));
+        }
+        Ok(ret)
+    }
+}
+
+/// Build a csv writer over a file or stdout (`-`) using the same delimiter and quote as the reader
+pub fn build_writer(output: &Path, opts: &CsvReaderOpts) -> anyhow::Result<Writer<Box<dyn Write>>> {
+    let writer = WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .from_writer(get_writer(output)?);
+    Ok(writer)
+}
+
+/// Position of the column called `name`
+pub fn column_index(headers: &StringRecord, name: &str) -> anyhow::Result<usize> {
+    headers
+        .iter()
+        .position(|h| h == name)
+        .ok_or_else(|| anyhow::anyhow!("Column '{}' not found", name))
+}
+
+/// Positions of the key columns of `input`, e.g. for `--key` or `--on`
+pub fn key_indices(
+    headers: &StringRecord,
+    names: &[String],
+    input: &Path,
+) -> anyhow::Result<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| {
+            headers.iter().position(|h| h == name).ok_or_else(|| {
+                anyhow::anyhow!("Key column '{}' not found in {}", name, display_path(input))
+            })
+        })
+        .collect()
+}
+
+/// Headerless input stays headerless, unless names were given by `--columns`
+pub fn writes_headers(opts: &CsvReaderOpts) -> bool {
+    opts.has_headers() || opts.columns.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn read(
+        content: &[u8],
+        opts: &CsvReaderOpts,
+    ) -> anyhow::Result<(NamedTempFile, Vec<StringRecord>)> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content)?;
+        let mut reader = build_reader(input.path(), opts)?;
+        read_headers(&mut reader, opts)?;
+        let records = read_records(reader, input.path(), opts, &mut Rejects::default())?
+            .collect::<anyhow::Result<Vec<_>>>();
+        Ok((input, records?))
+    }
+
+    #[test]
+    fn test_located_errors() -> anyhow::Result<()> {
+        let opts = CsvReaderOpts::default();
+        let err = read(b"name,kit\nBuffon,1\nPogba\n", &opts).unwrap_err();
+        let err = err.downcast::<CsvError>()?;
+        assert!(matches!(
+            err,
+            CsvError::UnequalLengths { line: 3, record: 2, expected: 2, found: 1, .. }
+        ));
+        assert!(err.to_string().ends_with(": line 3, record 2: found 1 fields, expected 2"));
+
+        // without an explicit encoding the stray byte would be detected as windows-1252
+        let utf8 = CsvReaderOpts { encoding: Some(encoding_rs::UTF_8), ..Default::default() };
+        let err = read(b"name,kit\nBuffon,1\n1,Pogb\xFF\n", &utf8).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with(": line 3, record 2, field 2 (kit): invalid UTF-8 after 4 bytes")
+        );
+
+        // a stray byte among valid multi-byte chars is reported where it is
+        let err = read(b"name,kit\nCaf\xC3\xA9,1\n1,Pogb\xFF\n", &Default::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with(": line 3, record 2, field 2 (kit): invalid UTF-8 after 4 bytes")
+        );
+
+        let opts = CsvReaderOpts { no_header: true, ..Default::default() };
+        let err = read(b"a,b\nc\n", &opts).unwrap_err();
+        assert!(err.to_string().ends_with(": line 2, record 2: found 1 fields, expected 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_bad_rows() -> anyhow::Result<()> {
+        let rejects = NamedTempFile::new()?;
+        let opts = CsvReaderOpts {
+            skip_bad_rows: true,
+            rejects: Some(rejects.path().to_path_buf()),
+            ..Default::default()
+        };
+        let content = b"name,kit\nBuffon,1\nPogba\nDybala,21,x\nCosta,11\n";
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content)?;
+        let mut handle = Rejects::default();
+        // two inputs sharing a rejects file append to it
+        for _ in 0..2 {
+            let mut reader = build_reader(input.path(), &opts)?;
+            read_headers(&mut reader, &opts)?;
+            let records = read_records(reader, input.path(), &opts, &mut handle)?
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            assert_eq!(records.len(), 2);
+        }
+        assert_eq!(handle.finish()?, vec![(rejects.path().to_path_buf(), 4)]);
+        let (_input, records) = read(content, &opts)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[1][0], "Costa");
+        let rejected = std::fs::read_to_string(rejects.path())?;
+        let lines = rejected.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "_line,_error,name,kit");
+        assert!(lines[1].starts_with("3,") && lines[1].ends_with(",Pogba"));
+        assert!(lines[2].starts_with("4,") && lines[2].ends_with(",Dybala,21,x"));
+        Ok(())
+    }
+}
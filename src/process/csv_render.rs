@@ -0,0 +1,133 @@
+use std::io::Write;
+use std::path::Path;
+
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::{Serde, ValueKind};
+use minijinja::{Environment, UndefinedBehavior};
+use serde_json::{Value, json};
+
+use super::csv_infer::infer_converter;
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use crate::{CsvReaderOpts, CsvTypeOpts, get_writer};
+
+/// Render every record of `input` through the jinja template in `template`, one after another.
+/// A record sees its columns by name, `row` (all columns, for names that are not identifiers),
+/// `headers` and its 1-based `index`. With `table` the template is rendered once and sees
+/// `headers` and `rows` instead
+pub fn process_csv_render(
+    input: &Path,
+    output: &Path,
+    template: &Path,
+    table: bool,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+    type_opts: &CsvTypeOpts,
+) -> anyhow::Result<()> {
+    let name = template.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let source = std::fs::read_to_string(template)?;
+    let env = environment(name.clone(), source)?;
+    let template = env.get_template(&name)?;
+
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let records = read_records(reader, input, opts, rejects)?;
+    let (converter, records) = infer_converter(records, &headers, type_opts)?;
+    let headers = Value::from(headers.iter().collect::<Vec<_>>());
+    let records = records.enumerate();
+
+    let mut writer = get_writer(output)?;
+    if table {
+        let rows = records
+            .map(|(idx, record)| converter.convert(&record?, idx))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let ctx = json!({ "headers": headers, "rows": rows });
+        writer.write_all(template.render(Serde(&ctx))?.as_bytes())?;
+    } else {
+        for (idx, record) in records {
+            let row = converter.convert(&record?, idx)?;
+            let mut ctx = row.as_object().cloned().unwrap_or_default();
+            // a column named like one of these is still reachable through `row`
+            ctx.insert("row".to_string(), row);
+            ctx.insert("headers".to_string(), headers.clone());
+            ctx.insert("index".to_string(), json!(idx + 1));
+            writer.write_all(template.render(Serde(&Value::Object(ctx)))?.as_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Templates keep their trailing newline so rendered records end up on their own lines,
+/// and a newline after a block tag is dropped. Undefined variables are errors
+fn environment(name: String, source: String) -> anyhow::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    env.set_syntax(SyntaxConfig::builder().trim_blocks(true).keep_trailing_newline(true).build()?);
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.add_filter("sql", sql_literal);
+    env.add_template_owned(name, source)?;
+    Ok(env)
+}
+
+/// `{{ value | sql }}` writes a SQL literal: NULL, a number, TRUE/FALSE or a quoted string
+fn sql_literal(value: minijinja::Value) -> String {
+    match value.kind() {
+        ValueKind::None | ValueKind::Undefined => "NULL".to_string(),
+        ValueKind::Bool if value.is_true() => "TRUE".to_string(),
+        ValueKind::Bool => "FALSE".to_string(),
+        ValueKind::Number => value.to_string(),
+        _ => format!("'{}'", value.to_string().replace('\'', "''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::{Builder, NamedTempFile};
+
+    use super::*;
+
+    fn render(template: &str, suffix: &str, table: bool) -> anyhow::Result<String> {
+        let mut file = Builder::new().suffix(suffix).tempfile()?;
+        file.write_all(template.as_bytes())?;
+        let mut input = NamedTempFile::new()?;
+        input.write_all(b"id,name,kit number,active\n1,O'Brien,7,true\n2,Ann,,false\n")?;
+        let output = NamedTempFile::new()?;
+        process_csv_render(
+            input.path(),
+            output.path(),
+            file.path(),
+            table,
+            &Default::default(),
+            &mut Default::default(),
+            &Default::default(),
+        )?;
+        Ok(std::fs::read_to_string(output.path())?)
+    }
+
+    #[test]
+    fn test_render_rows() -> anyhow::Result<()> {
+        let template = "INSERT INTO players VALUES ({{ id }}, {{ name | sql }}, {{ row['kit number'] | sql }}, {{ active | sql }});\n";
+        assert_eq!(
+            render(template, ".sql", false)?,
+            "INSERT INTO players VALUES (1, 'O''Brien', 7, TRUE);\nINSERT INTO players VALUES (2, 'Ann', NULL, FALSE);\n"
+        );
+
+        let template =
+            "{% if active %}\n{{ index }}/{{ headers | length }}: {{ name }}\n{% endif %}\n";
+        assert_eq!(render(template, ".txt", false)?, "1/4: O'Brien\n");
+        assert!(render("{{ nmae }}", ".txt", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_table() -> anyhow::Result<()> {
+        let template =
+            "<ul>\n{% for row in rows %}\n<li>{{ row.name }}</li>\n{% endfor %}\n</ul>\n";
+        assert_eq!(
+            render(template, ".html", true)?,
+            "<ul>\n<li>O&#x27;Brien</li>\n<li>Ann</li>\n</ul>\n"
+        );
+        Ok(())
+    }
+}
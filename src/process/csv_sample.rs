@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use csv::StringRecord;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use super::csv_reader::{
+    Rejects, build_reader, build_writer, column_index, read_headers, read_records, writes_headers,
+};
+use crate::CsvReaderOpts;
+
+/// How many records `csv sample` keeps, per group when sampling by a column
+#[derive(Debug, Clone, Copy)]
+pub enum SampleSize {
+    /// Exactly this many records, or all of them if there are fewer
+    Rows(usize),
+    /// This share of the records, between 0 and 1
+    Fraction(f64),
+}
+
+/// Write a random sample of `input` to `output`, keeping the input order.
+/// A row count streams through reservoir sampling, holding only the sample in memory.
+/// Without `by` a fraction keeps every record with that probability; with `by` it takes
+/// the rounded share of every group, which needs the record positions of the whole input
+pub fn process_csv_sample(
+    input: &Path,
+    output: &Path,
+    size: SampleSize,
+    by: Option<&str>,
+    seed: Option<u64>,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    if let SampleSize::Fraction(fraction) = size {
+        if !(0.0..=1.0).contains(&fraction) {
+            anyhow::bail!("The sample fraction must be between 0 and 1, got {}", fraction);
+        }
+    }
+    let mut rng = new_rng(seed);
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let by = by.map(|column| column_index(&headers, column)).transpose()?;
+    let records = read_records(reader, input, opts, rejects)?;
+
+    let mut writer = build_writer(output, opts)?;
+    if writes_headers(opts) {
+        writer.write_record(&headers)?;
+    }
+    let mut sample = match (size, by) {
+        (SampleSize::Fraction(fraction), None) => {
+            // nothing to remember, every record is decided on its own
+            for record in records {
+                let record = record?;
+                if rng.gen_bool(fraction) {
+                    writer.write_record(&record)?;
+                }
+            }
+            writer.flush()?;
+            return Ok(());
+        }
+        (SampleSize::Rows(rows), None) => {
+            let mut reservoir = Reservoir::new(rows);
+            for (idx, record) in records.enumerate() {
+                reservoir.observe(idx, record?, &mut rng);
+            }
+            reservoir.items
+        }
+        (SampleSize::Rows(rows), Some(by)) => {
+            let mut groups: HashMap<String, Reservoir> = HashMap::new();
+            for (idx, record) in records.enumerate() {
+                let record = record?;
+                let key = record.get(by).unwrap_or_default().to_string();
+                groups
+                    .entry(key)
+                    .or_insert_with(|| Reservoir::new(rows))
+                    .observe(idx, record, &mut rng);
+            }
+            groups.into_values().flat_map(|r| r.items).collect()
+        }
+        (SampleSize::Fraction(fraction), Some(by)) => {
+            let mut records = records.collect::<anyhow::Result<Vec<_>>>()?;
+            // groups in first seen order, so a seed always picks the same records
+            let mut order = Vec::new();
+            let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+            for (idx, record) in records.iter().enumerate() {
+                let key = record.get(by).unwrap_or_default();
+                groups
+                    .entry(key)
+                    .or_insert_with(|| {
+                        order.push(key);
+                        Vec::new()
+                    })
+                    .push(idx);
+            }
+            let mut picked = Vec::new();
+            for key in order {
+                let members = &groups[key];
+                let amount = (members.len() as f64 * fraction).round() as usize;
+                picked.extend(members.choose_multiple(&mut rng, amount).copied());
+            }
+            picked.into_iter().map(|idx| (idx, std::mem::take(&mut records[idx]))).collect()
+        }
+    };
+
+    sample.sort_unstable_by_key(|(idx, _)| *idx);
+    for (_, record) in sample {
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the records of `input` to `output` in random order, the whole input is held in memory
+pub fn process_csv_shuffle(
+    input: &Path,
+    output: &Path,
+    seed: Option<u64>,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<()> {
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let mut records =
+        read_records(reader, input, opts, rejects)?.collect::<anyhow::Result<Vec<_>>>()?;
+    records.shuffle(&mut new_rng(seed));
+
+    let mut writer = build_writer(output, opts)?;
+    if writes_headers(opts) {
+        writer.write_record(&headers)?;
+    }
+    for record in records {
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A seeded rng gives the same sequence on every run and platform.
+/// `StdRng` may change its algorithm between rand releases, ChaCha8 is fixed
+fn new_rng(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+/// Algorithm R: after `n` records, every record seen so far is kept with the same probability
+struct Reservoir {
+    size: usize,
+    seen: usize,
+    /// record index and record
+    items: Vec<(usize, StringRecord)>,
+}
+
+impl Reservoir {
+    fn new(size: usize) -> Self {
+        Self { size, seen: 0, items: Vec::new() }
+    }
+
+    fn observe(&mut self, idx: usize, record: StringRecord, rng: &mut ChaCha8Rng) {
+        if self.items.len() < self.size {
+            self.items.push((idx, record));
+        } else {
+            let slot = rng.gen_range(0..=self.seen);
+            if slot < self.size {
+                self.items[slot] = (idx, record);
+            }
+        }
+        self.seen += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    const JUVENTUS: &str = "assets/juventus.csv";
+
+    fn sample(size: SampleSize, by: Option<&str>, seed: u64) -> anyhow::Result<Vec<String>> {
+        let output = NamedTempFile::new()?;
+        process_csv_sample(
+            Path::new(JUVENTUS),
+            output.path(),
+            size,
+            by,
+            Some(seed),
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        Ok(std::fs::read_to_string(output.path())?.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_sample_rows() -> anyhow::Result<()> {
+        let lines = sample(SampleSize::Rows(5), None, 42)?;
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert_eq!(lines, sample(SampleSize::Rows(5), None, 42)?);
+        assert_ne!(lines, sample(SampleSize::Rows(5), None, 7)?);
+
+        // the input order is kept
+        let input = std::fs::read_to_string(JUVENTUS)?;
+        let positions =
+            lines[1..].iter().map(|l| input.lines().position(|i| i == l)).collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        assert_eq!(sample(SampleSize::Rows(100), None, 42)?.len(), 28);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_fraction() -> anyhow::Result<()> {
+        let lines = sample(SampleSize::Fraction(0.5), None, 42)?;
+        assert!(lines.len() > 1 && lines.len() < 28);
+        assert_eq!(lines, sample(SampleSize::Fraction(0.5), None, 42)?);
+        assert_eq!(sample(SampleSize::Fraction(0.0), None, 42)?.len(), 1);
+        assert_eq!(sample(SampleSize::Fraction(1.0), None, 42)?.len(), 28);
+        assert!(sample(SampleSize::Fraction(1.5), None, 42).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_stratified() -> anyhow::Result<()> {
+        let positions = |lines: &[String]| {
+            lines[1..].iter().map(|l| l.split(',').nth(1).unwrap().to_string()).collect::<Vec<_>>()
+        };
+        let lines = sample(SampleSize::Rows(1), Some("Position"), 42)?;
+        let found = positions(&lines);
+        let distinct = found.iter().collect::<HashSet<_>>();
+        assert_eq!(found.len(), distinct.len());
+        assert_eq!(lines, sample(SampleSize::Rows(1), Some("Position"), 42)?);
+
+        // half of 4 goalkeepers is 2, half of 5 centre-backs rounds to 3
+        let lines = sample(SampleSize::Fraction(0.5), Some("Position"), 42)?;
+        let found = positions(&lines);
+        assert_eq!(found.iter().filter(|p| *p == "Goalkeeper").count(), 2);
+        assert_eq!(found.iter().filter(|p| *p == "Centre-Back").count(), 3);
+        assert!(sample(SampleSize::Rows(1), Some("Team"), 42).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_seed_is_pinned() -> anyhow::Result<()> {
+        // a seed has to keep picking these rows across rand releases and platforms
+        let lines = sample(SampleSize::Rows(3), None, 42)?;
+        let names = lines[1..].iter().map(|l| l.split(',').next().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, ["Wojciech Szczesny", "Blaise Matuidi", "Federico Bernardeschi"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shuffle() -> anyhow::Result<()> {
+        let shuffle = |seed| -> anyhow::Result<Vec<String>> {
+            let output = NamedTempFile::new()?;
+            process_csv_shuffle(
+                Path::new(JUVENTUS),
+                output.path(),
+                Some(seed),
+                &Default::default(),
+                &mut Default::default(),
+            )?;
+            Ok(std::fs::read_to_string(output.path())?.lines().map(String::from).collect())
+        };
+        let lines = shuffle(42)?;
+        assert_eq!(lines, shuffle(42)?);
+        assert!(lines[1].starts_with("Gonzalo Higuaín,"));
+        assert!(lines[2].starts_with("Gianluigi Buffon,"));
+        let input = std::fs::read_to_string(JUVENTUS)?;
+        let mut sorted = lines.clone();
+        sorted[1..].sort();
+        let mut expected = input.lines().map(String::from).collect::<Vec<_>>();
+        expected[1..].sort();
+        assert_eq!(sorted, expected);
+        assert_ne!(lines, input.lines().collect::<Vec<_>>());
+        Ok(())
+    }
+}
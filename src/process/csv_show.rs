@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::Path;
+
+use csv::StringRecord;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::csv_filter::RecordPipeline;
+use super::csv_infer::TypeInference;
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use crate::{CsvColumnType, CsvProcessOpts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Box drawing borders, for terminals
+    Boxed,
+    /// Space separated columns, for pipes and files
+    Plain,
+}
+
+/// Render the csv as an aligned table, optionally only its first or last rows
+pub fn process_csv_show(
+    input: &Path,
+    opts: &CsvProcessOpts,
+    rejects: &mut Rejects,
+    head: Option<usize>,
+    tail: Option<usize>,
+    max_width: usize,
+    style: TableStyle,
+) -> anyhow::Result<String> {
+    let mut reader = build_reader(input, &opts.reader)?;
+    let headers = read_headers(&mut reader, &opts.reader)?;
+    let pipeline = RecordPipeline::new(&headers, &opts.filter)?;
+    let records = pipeline.apply(read_records(reader, input, &opts.reader, rejects)?);
+    let mut rows = VecDeque::new();
+    for record in records.take(head.unwrap_or(usize::MAX)) {
+        rows.push_back(record?);
+        // keep a ring buffer of the last rows so memory stays bounded
+        if tail.is_some_and(|n| rows.len() > n) {
+            rows.pop_front();
+        }
+    }
+    Ok(render_table(pipeline.headers(), rows.make_contiguous(), max_width, style))
+}
+
+/// Lay out `rows` under `headers`, numeric columns are right aligned.
+/// Cells wider than `max_width` terminal columns are truncated with `…`
+pub fn render_table(
+    headers: &StringRecord,
+    rows: &[StringRecord],
+    max_width: usize,
+    style: TableStyle,
+) -> String {
+    let cell = |value: &str| truncate(value, max_width);
+    let header_cells = headers.iter().map(cell).collect::<Vec<_>>();
+    let body = rows.iter().map(|r| r.iter().map(cell).collect::<Vec<_>>()).collect::<Vec<_>>();
+
+    let mut inference = TypeInference::default();
+    rows.iter().for_each(|r| inference.observe(r));
+    let numeric = inference
+        .finish(headers.len())
+        .iter()
+        .map(|ty| matches!(ty, CsvColumnType::Int | CsvColumnType::Float))
+        .collect::<Vec<_>>();
+
+    let mut widths = header_cells.iter().map(|c| c.width()).collect::<Vec<_>>();
+    for row in &body {
+        for (i, c) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(c.width());
+        }
+    }
+
+    let mut ret = String::new();
+    let line = |ret: &mut String, cells: &[String], right: &[bool]| {
+        let padded = widths.iter().enumerate().map(|(i, w)| {
+            let c = cells.get(i).map(String::as_str).unwrap_or_default();
+            let pad = " ".repeat(w - c.width());
+            if right.get(i) == Some(&true) {
+                format!("{}{}", pad, c)
+            } else {
+                format!("{}{}", c, pad)
+            }
+        });
+        let padded = padded.collect::<Vec<_>>();
+        match style {
+            TableStyle::Boxed => writeln!(ret, "│ {} │", padded.join(" │ ")),
+            TableStyle::Plain => writeln!(ret, "{}", padded.join("  ").trim_end()),
+        }
+        .unwrap();
+    };
+    let border = |ret: &mut String, left: &str, mid: &str, right: &str| {
+        let segments = widths.iter().map(|w| "─".repeat(w + 2)).collect::<Vec<_>>();
+        writeln!(ret, "{}{}{}", left, segments.join(mid), right).unwrap();
+    };
+
+    if style == TableStyle::Boxed {
+        border(&mut ret, "┌", "┬", "┐");
+    }
+    line(&mut ret, &header_cells, &[]);
+    if style == TableStyle::Boxed {
+        border(&mut ret, "├", "┼", "┤");
+    }
+    for row in &body {
+        line(&mut ret, row, &numeric);
+    }
+    if style == TableStyle::Boxed {
+        border(&mut ret, "└", "┴", "┘");
+    }
+    ret
+}
+
+/// Cut `value` to at most `max_width` terminal columns, wide (CJK) chars count as two.
+/// Control characters such as newlines would break the layout, so they become spaces
+fn truncate(value: &str, max_width: usize) -> String {
+    let value = value.replace(|c: char| c.is_control(), " ");
+    if value.width() <= max_width {
+        return value;
+    }
+    let mut ret = String::new();
+    let mut width = 0;
+    for c in value.chars() {
+        let w = c.width().unwrap_or(0);
+        // leave one column for the ellipsis
+        if width + w + 1 > max_width {
+            break;
+        }
+        ret.push(c);
+        width += w;
+    }
+    ret.push('…');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("Wojciech Szczesny", 8), "Wojciec…");
+        // every CJK char is two columns wide
+        assert_eq!(truncate("布冯布冯布冯", 6), "布冯…");
+        assert_eq!(truncate("布冯布冯布冯", 6).width(), 5);
+        assert_eq!(truncate("a\nb", 10), "a b");
+    }
+
+    #[test]
+    fn test_render_table() {
+        let headers = StringRecord::from(vec!["Name", "Kit"]);
+        let rows =
+            vec![StringRecord::from(vec!["布冯", "1"]), StringRecord::from(vec!["Pogba", "10"])];
+        assert_eq!(
+            render_table(&headers, &rows, 30, TableStyle::Plain),
+            "Name   Kit\n布冯     1\nPogba   10\n"
+        );
+        assert_eq!(
+            render_table(&headers, &rows, 30, TableStyle::Boxed),
+            "┌───────┬─────┐\n│ Name  │ Kit │\n├───────┼─────┤\n│ 布冯  │   1 │\n│ Pogba │  10 │\n└───────┴─────┘\n"
+        );
+    }
+
+    #[test]
+    fn test_process_csv_show_head_tail() -> anyhow::Result<()> {
+        let input = Path::new("assets/juventus.csv");
+        let opts = CsvProcessOpts::default();
+        let ret = process_csv_show(
+            input,
+            &opts,
+            &mut Default::default(),
+            Some(2),
+            None,
+            30,
+            TableStyle::Plain,
+        )?;
+        let lines = ret.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("Wojciech Szczesny"));
+
+        let ret = process_csv_show(
+            input,
+            &opts,
+            &mut Default::default(),
+            None,
+            Some(1),
+            30,
+            TableStyle::Plain,
+        )?;
+        let lines = ret.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("Mario Mandzukic"));
+        Ok(())
+    }
+}
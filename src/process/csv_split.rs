@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use csv::{StringRecord, Writer, WriterBuilder};
+
+use super::csv_reader::{
+    Rejects, build_reader, build_writer, column_index, read_headers, read_records, writes_headers,
+};
+use crate::CsvReaderOpts;
+
+/// Most files `--by` keeps open at once, the least recently written one is closed first
+/// and reopened for appending when its value comes back
+const MAX_OPEN_FILES: usize = 128;
+
+/// Writers open by `--by`, keyed by value, with the record number of their last write
+type OpenFiles = HashMap<String, (Writer<Box<dyn Write>>, usize)>;
+
+/// How `csv split` assigns records to files
+#[derive(Debug, Clone)]
+pub enum SplitBy {
+    /// At most this many records per file
+    Rows(usize),
+    /// A file per distinct value of this column
+    Column(String),
+}
+
+/// Split `input` into `{prefix}_{n}.csv` or `{prefix}_{value}.csv` files under `dir`.
+/// Returns the paths written, in creation order
+pub fn process_csv_split(
+    input: &Path,
+    dir: &Path,
+    prefix: &str,
+    by: &SplitBy,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    std::fs::create_dir_all(dir)?;
+    let create = |name: String| -> anyhow::Result<(PathBuf, Writer<Box<dyn Write>>)> {
+        let path = dir.join(format!("{}_{}.csv", prefix, name));
+        let mut writer = build_writer(&path, opts)?;
+        if writes_headers(opts) {
+            writer.write_record(&headers)?;
+        }
+        Ok((path, writer))
+    };
+
+    let mut paths = Vec::new();
+    match by {
+        SplitBy::Rows(0) => anyhow::bail!("The number of rows per file must be positive"),
+        SplitBy::Rows(rows) => {
+            let mut current: Option<Writer<Box<dyn Write>>> = None;
+            for (idx, record) in read_records(reader, input, opts, rejects)?.enumerate() {
+                if idx % rows == 0 {
+                    if let Some(mut writer) = current.take() {
+                        writer.flush()?;
+                    }
+                    let (path, writer) = create((idx / rows + 1).to_string())?;
+                    paths.push(path);
+                    current = Some(writer);
+                }
+                if let Some(writer) = current.as_mut() {
+                    writer.write_record(&record?)?;
+                }
+            }
+            if let Some(mut writer) = current {
+                writer.flush()?;
+            }
+        }
+        SplitBy::Column(column) => {
+            let idx = column_index(&headers, column)?;
+            // file of every value seen, and the names taken by them
+            let mut files: HashMap<String, PathBuf> = HashMap::new();
+            let mut names = HashSet::new();
+            let mut open = OpenFiles::new();
+            for (n, record) in read_records(reader, input, opts, rejects)?.enumerate() {
+                let record: StringRecord = record?;
+                let value = record.get(idx).unwrap_or_default();
+                if !open.contains_key(value) {
+                    if open.len() >= MAX_OPEN_FILES {
+                        close_oldest(&mut open)?;
+                    }
+                    let writer = match files.get(value) {
+                        Some(path) => append_writer(path, opts)?,
+                        None => {
+                            let (path, writer) = create(unique_name(value, &mut names))?;
+                            files.insert(value.to_string(), path.clone());
+                            paths.push(path);
+                            writer
+                        }
+                    };
+                    open.insert(value.to_string(), (writer, n));
+                }
+                if let Some((writer, last)) = open.get_mut(value) {
+                    writer.write_record(&record)?;
+                    *last = n;
+                }
+            }
+            for (writer, _) in open.values_mut() {
+                writer.flush()?;
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Close the least recently written file
+fn close_oldest(open: &mut OpenFiles) -> anyhow::Result<()> {
+    let Some(value) = open.iter().min_by_key(|(_, (_, last))| *last).map(|(v, _)| v.clone()) else {
+        return Ok(());
+    };
+    if let Some((mut writer, _)) = open.remove(&value) {
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Reopen a file written earlier, its header is already there
+fn append_writer(path: &Path, opts: &CsvReaderOpts) -> anyhow::Result<Writer<Box<dyn Write>>> {
+    let file = OpenOptions::new().append(true).open(path)?;
+    let writer: Box<dyn Write> = Box::new(BufWriter::new(file));
+    Ok(WriterBuilder::new().delimiter(opts.delimiter).quote(opts.quote).from_writer(writer))
+}
+
+/// The file name of a new value. Values that sanitize to a taken name, like `a b` and `a_b`,
+/// get a numbered suffix instead of sharing a file. Names are compared ignoring case, for
+/// case insensitive file systems
+fn unique_name(value: &str, names: &mut HashSet<String>) -> String {
+    let base = file_name(value);
+    let mut name = base.clone();
+    let mut count = 1;
+    while !names.insert(name.to_lowercase()) {
+        count += 1;
+        name = format!("{}_{}", base, count);
+    }
+    name
+}
+
+/// A column value usable as part of a file name
+fn file_name(value: &str) -> String {
+    if value.trim().is_empty() {
+        return "empty".to_string();
+    }
+    value
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by_rows() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let paths = process_csv_split(
+            Path::new("assets/juventus.csv"),
+            dir.path(),
+            "juventus",
+            &SplitBy::Rows(10),
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        assert_eq!(paths.len(), 3);
+        assert_eq!(paths[2], dir.path().join("juventus_3.csv"));
+        let last = std::fs::read_to_string(&paths[2])?;
+        assert!(last.starts_with("Name,Position,DOB,Nationality,Kit Number\n"));
+        assert_eq!(last.lines().count(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let paths = process_csv_split(
+            Path::new("assets/juventus.csv"),
+            dir.path(),
+            "players",
+            &SplitBy::Column("Position".to_string()),
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        assert_eq!(paths.len(), 10);
+        let goalkeepers = std::fs::read_to_string(dir.path().join("players_Goalkeeper.csv"))?;
+        assert_eq!(goalkeepers.lines().count(), 5);
+        assert!(dir.path().join("players_Central_Midfield.csv").exists());
+        assert_eq!(file_name(" "), "empty");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column_collisions() -> anyhow::Result<()> {
+        let mut content = "k,v\na b,1\na_b,2\nA_B,3\n".to_string();
+        // more values than open files, every one comes back after its file was closed
+        for round in 0..2 {
+            for i in 0..MAX_OPEN_FILES + 10 {
+                content.push_str(&format!("id{},{}\n", i, round));
+            }
+        }
+        let mut input = tempfile::NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        let dir = tempfile::tempdir()?;
+        let by = SplitBy::Column("k".to_string());
+        let paths = process_csv_split(
+            input.path(),
+            dir.path(),
+            "p",
+            &by,
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        assert_eq!(paths.len(), MAX_OPEN_FILES + 13);
+        assert_eq!(std::fs::read_to_string(dir.path().join("p_a_b.csv"))?, "k,v\na b,1\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("p_a_b_2.csv"))?, "k,v\na_b,2\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("p_A_B_3.csv"))?, "k,v\nA_B,3\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("p_id0.csv"))?, "k,v\nid0,0\nid0,1\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::csv_filter::compare_values;
+use super::csv_infer::{TypeInference, convert_value};
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use super::csv_show::{TableStyle, render_table};
+use super::csv_writer::record_writer;
+use crate::{CsvColumnType, CsvProcessOpts, CsvReaderOpts, OutputFormat, get_writer};
+
+/// Field names of a serialized `ColumnStats`, the columns of table formats
+const STATS_FIELDS: [&str; 11] = [
+    "name",
+    "type",
+    "count",
+    "nulls",
+    "distinct",
+    "min",
+    "max",
+    "mean",
+    "stddev",
+    "top",
+    "max_length",
+];
+
+/// Parquet and arrow types of `STATS_FIELDS`, min, max and top mix types across columns so they are text
+const STATS_TYPES: [CsvColumnType; 11] = [
+    CsvColumnType::String,
+    CsvColumnType::String,
+    CsvColumnType::Int,
+    CsvColumnType::Int,
+    CsvColumnType::Int,
+    CsvColumnType::String,
+    CsvColumnType::String,
+    CsvColumnType::Float,
+    CsvColumnType::Float,
+    CsvColumnType::String,
+    CsvColumnType::Int,
+];
+
+/// Profile of a single column
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: CsvColumnType,
+    pub count: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Value,
+    pub max: Value,
+    /// only for numeric columns
+    pub mean: Option<f64>,
+    /// sample standard deviation, only for numeric columns
+    pub stddev: Option<f64>,
+    pub top: Vec<TopValue>,
+    pub max_length: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Running totals of one column, numbers use Welford's algorithm so a single pass is enough
+#[derive(Debug, Default)]
+struct ColumnAccumulator {
+    counts: HashMap<String, usize>,
+    nulls: usize,
+    min: Option<String>,
+    max: Option<String>,
+    n: usize,
+    mean: f64,
+    m2: f64,
+    max_length: usize,
+}
+
+/// Profile every column of `input` and write it to `output`,
+/// as a table when `format` is `None`
+pub fn process_csv_stats(
+    input: &Path,
+    output: &Path,
+    opts: &CsvProcessOpts,
+    rejects: &mut Rejects,
+    top: usize,
+    format: Option<OutputFormat>,
+    style: TableStyle,
+) -> anyhow::Result<()> {
+    let stats = collect_stats(input, &opts.reader, rejects, top)?;
+    let mut writer = get_writer(output)?;
+    match format {
+        None => {
+            writer.write_all(stats_table(&stats, style).as_bytes())?;
+            writer.flush()?;
+            Ok(())
+        }
+        Some(format) => {
+            let headers = StringRecord::from(STATS_FIELDS.to_vec());
+            let types = Some(STATS_TYPES.to_vec());
+            let mut writer = record_writer(format, writer, &headers, types, &opts.columnar);
+            for column in &stats {
+                writer.write_record(&serde_json::to_value(column)?)?;
+            }
+            writer.finish()
+        }
+    }
+}
+
+fn collect_stats(
+    input: &Path,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+    top: usize,
+) -> anyhow::Result<Vec<ColumnStats>> {
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let mut columns = (0..headers.len()).map(|_| ColumnAccumulator::default()).collect::<Vec<_>>();
+    let mut inference = TypeInference::default();
+    let mut count = 0;
+    for record in read_records(reader, input, opts, rejects)? {
+        let record = record?;
+        inference.observe(&record);
+        for (column, value) in columns.iter_mut().zip(record.iter()) {
+            column.observe(value);
+        }
+        count += 1;
+    }
+    let types = inference.finish(headers.len());
+    let stats = headers
+        .iter()
+        .zip(columns)
+        .zip(types)
+        .map(|((name, column), ty)| column.finish(name, ty, count, top))
+        .collect();
+    Ok(stats)
+}
+
+impl ColumnAccumulator {
+    fn observe(&mut self, value: &str) {
+        if value.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.max_length = self.max_length.max(value.chars().count());
+        if self.min.as_deref().is_none_or(|min| compare_values(value, min).is_lt()) {
+            self.min = Some(value.to_string());
+        }
+        if self.max.as_deref().is_none_or(|max| compare_values(value, max).is_gt()) {
+            self.max = Some(value.to_string());
+        }
+        if let Ok(x) = value.parse::<f64>() {
+            self.n += 1;
+            let delta = x - self.mean;
+            self.mean += delta / self.n as f64;
+            self.m2 += delta * (x - self.mean);
+        }
+        *self.counts.entry(value.to_string()).or_default() += 1;
+    }
+
+    fn finish(self, name: &str, ty: CsvColumnType, count: usize, top: usize) -> ColumnStats {
+        let numeric = matches!(ty, CsvColumnType::Int | CsvColumnType::Float) && self.n > 0;
+        let typed = |value: Option<String>| {
+            value.and_then(|v| convert_value(&v, ty, true)).unwrap_or(Value::Null)
+        };
+        let mut frequent = self.counts.iter().collect::<Vec<_>>();
+        // ties are broken by value so the report is deterministic
+        frequent.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        ColumnStats {
+            name: name.to_string(),
+            ty,
+            count,
+            nulls: self.nulls,
+            distinct: self.counts.len(),
+            min: typed(self.min),
+            max: typed(self.max),
+            mean: numeric.then_some(self.mean),
+            stddev: (numeric && self.n > 1).then(|| (self.m2 / (self.n - 1) as f64).sqrt()),
+            top: frequent
+                .into_iter()
+                .take(top)
+                .map(|(value, count)| TopValue { value: value.clone(), count: *count })
+                .collect(),
+            max_length: self.max_length,
+        }
+    }
+}
+
+fn stats_table(stats: &[ColumnStats], style: TableStyle) -> String {
+    let headers = StringRecord::from(vec![
+        "column", "type", "count", "nulls", "distinct", "min", "max", "mean", "stddev", "top",
+        "max_len",
+    ]);
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    };
+    let float = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_default();
+    let rows = stats
+        .iter()
+        .map(|s| {
+            let top = s.top.iter().map(|t| format!("{} ({})", t.value, t.count));
+            StringRecord::from(vec![
+                s.name.clone(),
+                s.ty.to_string(),
+                s.count.to_string(),
+                s.nulls.to_string(),
+                s.distinct.to_string(),
+                text(&s.min),
+                text(&s.max),
+                float(s.mean),
+                float(s.stddev),
+                top.collect::<Vec<_>>().join(", "),
+                s.max_length.to_string(),
+            ])
+        })
+        .collect::<Vec<_>>();
+    render_table(&headers, &rows, 60, style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_stats() -> anyhow::Result<()> {
+        let stats = collect_stats(
+            Path::new("assets/juventus.csv"),
+            &Default::default(),
+            &mut Default::default(),
+            1,
+        )?;
+        assert_eq!(stats.len(), 5);
+
+        let name = &stats[0];
+        assert_eq!(name.ty, CsvColumnType::String);
+        assert_eq!(name.count, 27);
+        assert_eq!(name.distinct, 27);
+        assert_eq!(name.max_length, 21);
+        assert_eq!(name.mean, None);
+
+        let nationality = &stats[3];
+        assert_eq!(nationality.top[0].value, "Italy");
+        assert_eq!(nationality.top[0].count, 8);
+
+        let kit = &stats[4];
+        assert_eq!(kit.ty, CsvColumnType::Int);
+        assert_eq!(kit.nulls, 0);
+        assert_eq!(kit.min, 1);
+        assert_eq!(kit.max, 77);
+        assert!((kit.mean.unwrap() - 18.2222).abs() < 1e-3);
+        assert!((kit.stddev.unwrap() - 15.5695).abs() < 1e-3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_nulls_and_dates() -> anyhow::Result<()> {
+        let mut input = tempfile::NamedTempFile::new()?;
+        input.write_all(b"joined,score\n2020-01-02,\n2019-05-06,1.5\n,2.5\n")?;
+        let stats = collect_stats(input.path(), &Default::default(), &mut Default::default(), 3)?;
+        assert_eq!(stats[0].ty, CsvColumnType::Date);
+        assert_eq!(stats[0].nulls, 1);
+        assert_eq!(stats[0].min, "2019-05-06");
+        assert_eq!(stats[1].ty, CsvColumnType::Float);
+        assert_eq!(stats[1].mean, Some(2.0));
+        Ok(())
+    }
+}
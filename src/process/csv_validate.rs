@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::csv_filter::compare_same_kind;
+use super::csv_infer::convert_value;
+use super::csv_reader::{Rejects, build_reader, read_headers, read_records};
+use crate::{CsvColumnType, CsvReaderOpts, get_reader};
+
+/// Expected shape of a csv file, loaded from yaml:
+///
+/// ```yaml
+/// columns:
+///   - name: Kit Number
+///     type: int
+///     min: 1
+///     max: 99
+///     unique: true
+///   - name: Position
+///     enum: [Goalkeeper, Centre-Back]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvSchema {
+    pub columns: Vec<ColumnRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnRule {
+    pub name: String,
+    /// the column must be present in the header
+    #[serde(default = "default_true")]
+    pub required: bool,
+    /// empty values are allowed
+    #[serde(default = "default_true")]
+    pub nullable: bool,
+    #[serde(rename = "type")]
+    pub ty: Option<CsvColumnType>,
+    /// a regex every non-empty value must match
+    pub pattern: Option<String>,
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<String>>,
+    /// numbers and dates compare by value
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+/// A single schema violation, `row` is the 1-based line in the input
+#[derive(Debug, Serialize)]
+pub struct Violation {
+    pub row: u64,
+    /// 1-based column number, absent for violations of the whole header
+    pub column: Option<usize>,
+    pub name: String,
+    pub value: Option<String>,
+    pub message: String,
+}
+
+/// A column rule resolved against the actual header
+struct ColumnCheck<'a> {
+    rule: &'a ColumnRule,
+    idx: usize,
+    pattern: Option<Regex>,
+    min: Option<String>,
+    max: Option<String>,
+    seen: HashSet<String>,
+}
+
+/// Check every record of `input` against the yaml schema and return all violations
+pub fn process_csv_validate(
+    input: &Path,
+    schema: &Path,
+    opts: &CsvReaderOpts,
+    rejects: &mut Rejects,
+) -> anyhow::Result<Vec<Violation>> {
+    let schema: CsvSchema = serde_yaml::from_reader(get_reader(schema)?)?;
+    let mut reader = build_reader(input, opts)?;
+    let headers = read_headers(&mut reader, opts)?;
+
+    let mut violations = Vec::new();
+    let mut checks = Vec::new();
+    for rule in &schema.columns {
+        match headers.iter().position(|h| h == rule.name) {
+            Some(idx) => checks.push(ColumnCheck::new(rule, idx)?),
+            None if rule.required => violations.push(Violation {
+                row: 1,
+                column: None,
+                name: rule.name.clone(),
+                value: None,
+                message: "required column is missing".to_string(),
+            }),
+            None => {}
+        }
+    }
+
+    for record in read_records(reader, input, opts, rejects)? {
+        let record = record?;
+        let row = record.position().map_or(0, |p| p.line());
+        for check in &mut checks {
+            let value = record.get(check.idx).unwrap_or_default();
+            for message in check.check(value) {
+                violations.push(Violation {
+                    row,
+                    column: Some(check.idx + 1),
+                    name: check.rule.name.clone(),
+                    value: Some(value.to_string()),
+                    message,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+impl<'a> ColumnCheck<'a> {
+    fn new(rule: &'a ColumnRule, idx: usize) -> anyhow::Result<Self> {
+        // anchor the pattern so it has to match the whole value
+        let pattern =
+            rule.pattern.as_ref().map(|p| Regex::new(&format!("^(?:{})$", p))).transpose()?;
+        Ok(Self {
+            rule,
+            idx,
+            pattern,
+            min: rule.min.as_ref().map(bound_text),
+            max: rule.max.as_ref().map(bound_text),
+            seen: HashSet::new(),
+        })
+    }
+
+    fn check(&mut self, value: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        if value.is_empty() {
+            if !self.rule.nullable {
+                ret.push("value must not be empty".to_string());
+            }
+            return ret;
+        }
+        if let Some(ty) = self.rule.ty {
+            if convert_value(value, ty, true).is_none() {
+                ret.push(format!("value is not a valid {}", ty));
+            }
+        }
+        if let (Some(pattern), Some(text)) = (&self.pattern, &self.rule.pattern) {
+            if !pattern.is_match(value) {
+                ret.push(format!("value does not match pattern '{}'", text));
+            }
+        }
+        if let Some(allowed) = &self.rule.allowed {
+            if !allowed.iter().any(|a| a == value) {
+                ret.push(format!("value is not one of {}", allowed.join(", ")));
+            }
+        }
+        // a bound only applies to values of its own kind, anything else is reported once
+        let mut mismatch = None;
+        if let Some(min) = &self.min {
+            match compare_same_kind(value, min) {
+                Ok(ordering) if ordering.is_lt() => ret.push(format!("value is less than {}", min)),
+                Ok(_) => {}
+                Err(kind) => mismatch = Some((kind, min)),
+            }
+        }
+        if let Some(max) = &self.max {
+            match compare_same_kind(value, max) {
+                Ok(ordering) if ordering.is_gt() => {
+                    ret.push(format!("value is greater than {}", max))
+                }
+                Ok(_) => {}
+                Err(kind) => mismatch = mismatch.or(Some((kind, max))),
+            }
+        }
+        if let Some((kind, bound)) = mismatch {
+            ret.push(format!("value is not a {} to compare with {}", kind, bound));
+        }
+        if self.rule.unique && !self.seen.insert(value.to_string()) {
+            ret.push("value is not unique".to_string());
+        }
+        ret
+    }
+}
+
+fn bound_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn validate(schema: &str, content: &str) -> anyhow::Result<Vec<Violation>> {
+        let mut schema_file = NamedTempFile::new()?;
+        schema_file.write_all(schema.as_bytes())?;
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        process_csv_validate(
+            input.path(),
+            schema_file.path(),
+            &Default::default(),
+            &mut Default::default(),
+        )
+    }
+
+    #[test]
+    fn test_validate_juventus() -> anyhow::Result<()> {
+        let schema = r#"
+columns:
+  - name: Name
+    nullable: false
+    unique: true
+  - name: DOB
+    type: date
+  - name: Kit Number
+    type: int
+    min: 1
+    max: 99
+    unique: true
+  - name: Nationality
+    pattern: "[A-Z][A-Za-z -]+"
+"#;
+        let content = std::fs::read_to_string("assets/juventus.csv")?;
+        assert!(validate(schema, &content)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_violations() -> anyhow::Result<()> {
+        let schema = r#"
+columns:
+  - name: id
+    type: int
+    unique: true
+    min: 1
+  - name: role
+    enum: [admin, user]
+  - name: email
+    nullable: false
+    pattern: ".+@.+"
+  - name: missing
+"#;
+        let content = "id,role,email\n1,admin,a@x.io\n1,root,\nx,user,bob\n";
+        let violations = validate(schema, content)?;
+        let found =
+            violations.iter().map(|v| (v.row, v.column, v.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (1, None, "required column is missing"),
+                (3, Some(1), "value is not unique"),
+                (3, Some(2), "value is not one of admin, user"),
+                (3, Some(3), "value must not be empty"),
+                (4, Some(1), "value is not a valid int"),
+                (4, Some(1), "value is not a number to compare with 1"),
+                (4, Some(3), "value does not match pattern '.+@.+'"),
+            ]
+        );
+        Ok(())
+    }
+}
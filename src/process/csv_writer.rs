@@ -0,0 +1,346 @@
+use std::io::Write;
+
+use csv::StringRecord;
+use serde_json::{Value, json};
+
+use super::csv_columnar::ColumnarWriter;
+use super::csv_xlsx::XlsxWriter;
+use crate::CsvColumnType;
+use crate::cli::{CsvColumnarOpts, OutputFormat};
+
+/// Writes converted records one at a time, so memory does not depend on the input size
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()>;
+    /// Write any trailing content and flush the underlying writer
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// `headers` gives the column order of table formats (markdown, html),
+/// `types` and `columnar` the schema and layout of parquet and arrow
+pub fn record_writer(
+    format: OutputFormat,
+    writer: Box<dyn Write>,
+    headers: &StringRecord,
+    types: Option<Vec<CsvColumnType>>,
+    columnar: &CsvColumnarOpts,
+) -> Box<dyn RecordWriter> {
+    let headers = headers.iter().map(String::from).collect();
+    match format {
+        OutputFormat::Json => Box::new(JsonWriter { writer, count: 0 }),
+        OutputFormat::Yaml => Box::new(YamlWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Toml => Box::new(TomlWriter { writer, count: 0 }),
+        OutputFormat::Markdown => Box::new(MarkdownWriter { writer, headers, started: false }),
+        OutputFormat::Html => Box::new(HtmlWriter { writer, headers, started: false }),
+        OutputFormat::Xlsx => Box::new(XlsxWriter::new(writer, headers)),
+        // without `types` column types are inferred from the first row group
+        OutputFormat::Parquet | OutputFormat::Arrow => {
+            Box::new(ColumnarWriter::new(format, writer, headers, types, columnar.clone()))
+        }
+    }
+}
+
+/// A pretty printed json array, written element by element
+struct JsonWriter {
+    writer: Box<dyn Write>,
+    count: usize,
+}
+
+/// A yaml sequence, every record is written as one `- ` item
+struct YamlWriter {
+    writer: Box<dyn Write>,
+    count: usize,
+}
+
+/// One compact json object per line
+struct NdjsonWriter {
+    writer: Box<dyn Write>,
+}
+
+/// Every record becomes a `[[rows]]` table
+struct TomlWriter {
+    writer: Box<dyn Write>,
+    count: usize,
+}
+
+struct MarkdownWriter {
+    writer: Box<dyn Write>,
+    headers: Vec<String>,
+    started: bool,
+}
+
+struct HtmlWriter {
+    writer: Box<dyn Write>,
+    headers: Vec<String>,
+    started: bool,
+}
+
+impl RecordWriter for JsonWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        let sep = if self.count == 0 { "[" } else { "," };
+        // json strings never contain raw newlines, so indenting every line nests the element
+        let element = serde_json::to_string_pretty(record)?.replace('\n', "\n  ");
+        write!(self.writer, "{}\n  {}", sep, element)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        writeln!(self.writer, "{}", end)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl RecordWriter for YamlWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        // a one element sequence serializes as a single `- ` item
+        serde_yaml::to_writer(&mut self.writer, std::slice::from_ref(record))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if self.count == 0 {
+            writeln!(self.writer, "[]")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl RecordWriter for NdjsonWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl RecordWriter for TomlWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        if self.count > 0 {
+            writeln!(self.writer)?;
+        }
+        // every chunk is a valid `[[rows]]` entry, so they can simply be concatenated
+        let table = toml::to_string(&json!({ "rows": [strip_nulls(record)] }))?;
+        self.writer.write_all(table.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl MarkdownWriter {
+    fn write_header(&mut self) -> anyhow::Result<()> {
+        let cells = self.headers.iter().map(|h| escape_markdown(h)).collect::<Vec<_>>();
+        writeln!(self.writer, "| {} |", cells.join(" | "))?;
+        writeln!(self.writer, "|{}", " --- |".repeat(self.headers.len()))?;
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl RecordWriter for MarkdownWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        let cells = self
+            .headers
+            .iter()
+            .map(|h| escape_markdown(&cell_text(&record[h.as_str()])))
+            .collect::<Vec<_>>();
+        writeln!(self.writer, "| {} |", cells.join(" | "))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl HtmlWriter {
+    fn write_header(&mut self) -> anyhow::Result<()> {
+        writeln!(self.writer, "<!DOCTYPE html>")?;
+        writeln!(self.writer, "<html>")?;
+        writeln!(self.writer, "<head>")?;
+        writeln!(self.writer, "<meta charset=\"utf-8\">")?;
+        writeln!(
+            self.writer,
+            "<style>table {{ border-collapse: collapse; }} th, td {{ border: 1px solid #ccc; padding: 4px 8px; }}</style>"
+        )?;
+        writeln!(self.writer, "</head>")?;
+        writeln!(self.writer, "<body>")?;
+        writeln!(self.writer, "<table>")?;
+        writeln!(self.writer, "<thead>")?;
+        let cells = self.headers.iter().map(|h| format!("<th>{}</th>", escape_html(h)));
+        writeln!(self.writer, "<tr>{}</tr>", cells.collect::<String>())?;
+        writeln!(self.writer, "</thead>")?;
+        writeln!(self.writer, "<tbody>")?;
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl RecordWriter for HtmlWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        let cells = self
+            .headers
+            .iter()
+            .map(|h| format!("<td>{}</td>", escape_html(&cell_text(&record[h.as_str()]))));
+        writeln!(self.writer, "<tr>{}</tr>", cells.collect::<String>())?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+        writeln!(self.writer, "</tbody>")?;
+        writeln!(self.writer, "</table>")?;
+        writeln!(self.writer, "</body>")?;
+        writeln!(self.writer, "</html>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// toml has no null, so null values are left out
+fn strip_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), strip_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => {
+            Value::Array(items.iter().filter(|v| !v.is_null()).map(strip_nulls).collect())
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Text of a table cell, strings are written without quotes
+pub fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Markdown renderers pass html through, so `&`, `<` and `>` are escaped before line breaks
+/// become `<br>`. Backslashes are escaped before pipes so a trailing `\` can not swallow a separator
+fn escape_markdown(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('\\', "\\\\")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+fn escape_html(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// A writer whose content can still be read after it is moved into a `RecordWriter`
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn write_all(format: OutputFormat, records: &[Value]) -> anyhow::Result<String> {
+        let buf = SharedBuf::default();
+        let headers = StringRecord::from(vec!["a", "b"]);
+        let mut writer =
+            record_writer(format, Box::new(buf.clone()), &headers, None, &Default::default());
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        let content = buf.0.borrow().clone();
+        Ok(String::from_utf8(content)?)
+    }
+
+    #[test]
+    fn test_json_writer() -> anyhow::Result<()> {
+        let records = vec![json!({"a": 1, "b": "x"}), json!({"a": 2, "b": null})];
+        let content = write_all(OutputFormat::Json, &records)?;
+        assert_eq!(content, format!("{}\n", serde_json::to_string_pretty(&records)?));
+        assert_eq!(write_all(OutputFormat::Json, &[])?, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_writer() -> anyhow::Result<()> {
+        let records = vec![json!({"a": 1, "b": "x"}), json!({"a": 2, "b": null})];
+        let content = write_all(OutputFormat::Yaml, &records)?;
+        assert_eq!(content, serde_yaml::to_string(&records)?);
+        assert_eq!(write_all(OutputFormat::Yaml, &[])?, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_writers_escape() -> anyhow::Result<()> {
+        let records = vec![json!({"a": "x|y", "b": "<b>"})];
+        let content = write_all(OutputFormat::Markdown, &records)?;
+        assert_eq!(content, "| a | b |\n| --- | --- |\n| x\\|y | &lt;b&gt; |\n");
+        let content = write_all(OutputFormat::Markdown, &[json!({"a": "1 < 2\nok", "b": ""})])?;
+        assert!(content.ends_with("| 1 &lt; 2<br>ok |  |\n"));
+        let content = write_all(OutputFormat::Markdown, &[json!({"a": "&lt; a|b", "b": "C:\\"})])?;
+        assert!(content.ends_with("| &amp;lt; a\\|b | C:\\\\ |\n"));
+        let content = write_all(OutputFormat::Html, &records)?;
+        assert!(content.contains("<tr><td>x|y</td><td>&lt;b&gt;</td></tr>"));
+        Ok(())
+    }
+}
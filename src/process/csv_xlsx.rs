@@ -0,0 +1,173 @@
+use std::io::Write;
+use std::path::Path;
+
+use calamine::{Data, Reader, open_workbook_auto};
+use chrono::NaiveTime;
+use csv::WriterBuilder;
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use serde_json::Value;
+use unicode_width::UnicodeWidthStr;
+
+use super::csv_writer::{RecordWriter, cell_text};
+use crate::CsvReaderOpts;
+
+/// Widest column Excel is asked for, in characters
+const MAX_COLUMN_WIDTH: usize = 60;
+
+/// Inputs read through calamine instead of the csv parser
+pub fn is_spreadsheet(path: &Path) -> bool {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    matches!(ext.to_lowercase().as_str(), "xlsx" | "xlsm" | "xlsb" | "xls" | "ods")
+}
+
+/// Render a worksheet as csv text using the reader's delimiter and quote,
+/// so it runs through the same pipeline as any csv file
+pub fn read_sheet(path: &Path, opts: &CsvReaderOpts) -> anyhow::Result<Vec<u8>> {
+    let mut workbook = open_workbook_auto(path)?;
+    let names = workbook.sheet_names();
+    let name = match opts.sheet.as_deref() {
+        None => names.first().cloned(),
+        Some(sheet) => names.iter().find(|n| n.as_str() == sheet).cloned().or_else(|| {
+            // a number picks the sheet by position, starting at 1
+            let idx = sheet.parse::<usize>().ok()?;
+            names.get(idx.checked_sub(1)?).cloned()
+        }),
+    };
+    let Some(name) = name else {
+        anyhow::bail!(
+            "Sheet '{}' not found in {}, available: {}",
+            opts.sheet.as_deref().unwrap_or_default(),
+            path.display(),
+            names.join(", ")
+        );
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .double_quote(opts.escape.is_none())
+        .escape(opts.escape.unwrap_or(b'\\'))
+        .from_writer(Vec::new());
+    for row in range.rows() {
+        writer.write_record(row.iter().map(data_text))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Excel stores every number as a float, integral ones are written without a fraction
+fn data_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+        Data::Int(i) => i.to_string(),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => (*f as i64).to_string(),
+        Data::Float(f) => f.to_string(),
+        Data::Bool(b) => b.to_string(),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) if dt.time() == NaiveTime::MIN => dt.date().format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => dt.as_f64().to_string(),
+        },
+        Data::Error(e) => e.to_string(),
+    }
+}
+
+/// A workbook with a single sheet: bold header row, autofilter and fitted column widths.
+/// The file is only complete once finished, so rows are kept in the worksheet until then
+pub struct XlsxWriter {
+    writer: Box<dyn Write>,
+    headers: Vec<String>,
+    sheet: Worksheet,
+    rows: u32,
+    widths: Vec<usize>,
+}
+
+impl XlsxWriter {
+    pub fn new(writer: Box<dyn Write>, headers: Vec<String>) -> Self {
+        let widths = headers.iter().map(|h| h.width()).collect();
+        Self { writer, headers, sheet: Worksheet::new(), rows: 0, widths }
+    }
+}
+
+impl RecordWriter for XlsxWriter {
+    fn write_record(&mut self, record: &Value) -> anyhow::Result<()> {
+        self.rows += 1;
+        let row = self.rows;
+        for (col, header) in self.headers.iter().enumerate() {
+            let value = record.get(header).unwrap_or(&Value::Null);
+            let col_num = col as u16;
+            match value {
+                Value::Null => continue,
+                Value::Bool(b) => self.sheet.write_boolean(row, col_num, *b)?,
+                Value::Number(n) => {
+                    self.sheet.write_number(row, col_num, n.as_f64().unwrap_or_default())?
+                }
+                // nested values of --unflatten are kept as json text
+                _ => self.sheet.write_string(row, col_num, cell_text(value))?,
+            };
+            self.widths[col] = self.widths[col].max(cell_text(value).width());
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        let bold = Format::new().set_bold();
+        for (col, header) in self.headers.iter().enumerate() {
+            self.sheet.write_string_with_format(0, col as u16, header, &bold)?;
+            // a little room for the autofilter button
+            let width = (self.widths[col] + 3).min(MAX_COLUMN_WIDTH);
+            self.sheet.set_column_width(col as u16, width as f64)?;
+        }
+        if !self.headers.is_empty() {
+            self.sheet.autofilter(0, 0, self.rows, self.headers.len() as u16 - 1)?;
+        }
+        let mut workbook = Workbook::new();
+        workbook.push_worksheet(self.sheet);
+        self.writer.write_all(&workbook.save_to_buffer()?)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::{OutputFormat, process_csv};
+
+    #[test]
+    fn test_xlsx_round_trip() -> anyhow::Result<()> {
+        let xlsx = Builder::new().suffix(".xlsx").tempfile()?;
+        process_csv(
+            Path::new("assets/juventus.csv"),
+            xlsx.path(),
+            OutputFormat::Xlsx,
+            false,
+            &Default::default(),
+            &mut Default::default(),
+        )?;
+        assert!(is_spreadsheet(xlsx.path()));
+
+        let csv = String::from_utf8(read_sheet(xlsx.path(), &Default::default())?)?;
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 28);
+        assert_eq!(lines[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert_eq!(lines[1], "Wojciech Szczesny,Goalkeeper,\"Apr 18, 1990 (29)\",Poland,1");
+
+        let opts = CsvReaderOpts { sheet: Some("1".into()), ..Default::default() };
+        assert_eq!(read_sheet(xlsx.path(), &opts)?, csv.as_bytes());
+        let opts = CsvReaderOpts { sheet: Some("Players".into()), ..Default::default() };
+        assert!(read_sheet(xlsx.path(), &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_text() {
+        assert_eq!(data_text(&Data::Float(7.0)), "7");
+        assert_eq!(data_text(&Data::Float(1.5)), "1.5");
+        assert_eq!(data_text(&Data::Empty), "");
+        assert_eq!(data_text(&Data::Bool(true)), "true");
+    }
+}
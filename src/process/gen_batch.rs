@@ -0,0 +1,100 @@
+use serde::{Serialize, Serializer};
+
+use crate::GenPassFormat;
+
+/// One password of a `genpass --count` batch
+#[derive(Debug, Serialize)]
+pub struct GeneratedPassword {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub password: String,
+    /// zxcvbn score from 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    /// bits of randomness in the generator, not an estimate from the password itself
+    #[serde(serialize_with = "round_bits")]
+    pub entropy: f64,
+}
+
+/// Render a batch of passwords. Text has one password per line, prefixed by its label and a tab.
+/// Json and csv carry the score and entropy as well, csv has a `label` column only if a
+/// password has a label, so the output reads back with `rcli csv`
+pub fn format_passwords(
+    passwords: &[GeneratedPassword],
+    format: GenPassFormat,
+) -> anyhow::Result<String> {
+    let ret = match format {
+        GenPassFormat::Text => passwords
+            .iter()
+            .map(|p| match &p.label {
+                Some(label) => format!("{}\t{}\n", label, p.password),
+                None => format!("{}\n", p.password),
+            })
+            .collect(),
+        GenPassFormat::Json => serde_json::to_string_pretty(passwords)? + "\n",
+        GenPassFormat::Csv => {
+            let labeled = passwords.iter().any(|p| p.label.is_some());
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            let mut headers = vec!["password", "score", "entropy"];
+            if labeled {
+                headers.insert(0, "label");
+            }
+            writer.write_record(&headers)?;
+            for p in passwords {
+                let mut record =
+                    vec![p.password.clone(), p.score.to_string(), format!("{:.1}", p.entropy)];
+                if labeled {
+                    record.insert(0, p.label.clone().unwrap_or_default());
+                }
+                writer.write_record(&record)?;
+            }
+            String::from_utf8(writer.into_inner()?)?
+        }
+    };
+    Ok(ret)
+}
+
+/// a tenth of a bit is precise enough and keeps json and csv alike
+fn round_bits<S: Serializer>(bits: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64((bits * 10.0).round() / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(labels: &[Option<&str>]) -> Vec<GeneratedPassword> {
+        labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| GeneratedPassword {
+                label: label.map(String::from),
+                password: format!("pw,{}", i),
+                score: 4,
+                entropy: 71.46,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_format_passwords() -> anyhow::Result<()> {
+        let passwords = batch(&[Some("alice"), None]);
+        assert_eq!(format_passwords(&passwords, GenPassFormat::Text)?, "alice\tpw,0\npw,1\n");
+        assert_eq!(
+            format_passwords(&passwords, GenPassFormat::Csv)?,
+            "label,password,score,entropy\nalice,\"pw,0\",4,71.5\n,\"pw,1\",4,71.5\n"
+        );
+        let value: serde_json::Value =
+            serde_json::from_str(&format_passwords(&passwords, GenPassFormat::Json)?)?;
+        assert_eq!(value[0]["label"], "alice");
+        assert_eq!(value[1].get("label"), None);
+        assert_eq!(value[1]["score"], 4);
+        assert_eq!(value[1]["entropy"], 71.5);
+
+        let passwords = batch(&[None]);
+        assert_eq!(
+            format_passwords(&passwords, GenPassFormat::Csv)?,
+            "password,score,entropy\n\"pw,0\",4,71.5\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+use crate::{GenPassFormat, get_reader};
+
+/// The zxcvbn verdict on one password of `genpass check`. The password itself is left out,
+/// so reports can go to CI logs
+#[derive(Debug, Serialize)]
+pub struct PasswordCheck {
+    /// 1-based line of the password in the input
+    pub line: usize,
+    /// from 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_times: CrackTimes,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Time to crack a password in the attack scenarios of zxcvbn
+#[derive(Debug, Serialize)]
+pub struct CrackTimes {
+    /// online attack on a service that limits attempts, 100 per hour
+    pub online_throttled: String,
+    /// online attack without limits, 10 per second
+    pub online_unthrottled: String,
+    /// offline attack on a slow hash like bcrypt, 10k per second
+    pub offline_slow_hash: String,
+    /// offline attack on a fast hash like md5, 10 billion per second
+    pub offline_fast_hash: String,
+}
+
+/// Score every non-empty line of `input` (a file or `-` for stdin) as a password.
+/// `user_inputs` are words an attacker would try first, e.g. the user name and email
+pub fn process_genpass_check(
+    input: &Path,
+    user_inputs: &[String],
+) -> anyhow::Result<Vec<PasswordCheck>> {
+    let user_inputs = user_inputs.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut ret = Vec::new();
+    for (idx, line) in BufReader::new(get_reader(input)?).lines().enumerate() {
+        // only the line ending is stripped, spaces can be part of a password
+        let line = line?;
+        let password = line.strip_suffix('\r').unwrap_or(&line);
+        if password.is_empty() {
+            continue;
+        }
+        let estimate = zxcvbn(password, &user_inputs);
+        let times = estimate.crack_times();
+        let feedback = estimate.feedback();
+        ret.push(PasswordCheck {
+            line: idx + 1,
+            score: u8::from(estimate.score()),
+            guesses_log10: estimate.guesses_log10(),
+            crack_times: CrackTimes {
+                online_throttled: times.online_throttling_100_per_hour().to_string(),
+                online_unthrottled: times.online_no_throttling_10_per_second().to_string(),
+                offline_slow_hash: times.offline_slow_hashing_1e4_per_second().to_string(),
+                offline_fast_hash: times.offline_fast_hashing_1e10_per_second().to_string(),
+            },
+            warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+            suggestions: feedback
+                .map(|f| f.suggestions().iter().map(ToString::to_string).collect())
+                .unwrap_or_default(),
+        });
+    }
+    Ok(ret)
+}
+
+/// Text is a short block per password, csv joins the suggestions with spaces
+pub fn format_checks(checks: &[PasswordCheck], format: GenPassFormat) -> anyhow::Result<String> {
+    let ret = match format {
+        GenPassFormat::Text => {
+            let mut ret = String::new();
+            for check in checks {
+                let times = &check.crack_times;
+                ret.push_str(&format!("line {}: score {}/4\n", check.line, check.score));
+                ret.push_str(&format!(
+                    "  crack time: {} (online, throttled), {} (online), {} (offline, slow hash), {} (offline, fast hash)\n",
+                    times.online_throttled,
+                    times.online_unthrottled,
+                    times.offline_slow_hash,
+                    times.offline_fast_hash
+                ));
+                if let Some(warning) = &check.warning {
+                    ret.push_str(&format!("  warning: {}\n", warning));
+                }
+                for suggestion in &check.suggestions {
+                    ret.push_str(&format!("  suggestion: {}\n", suggestion));
+                }
+            }
+            ret
+        }
+        GenPassFormat::Json => serde_json::to_string_pretty(checks)? + "\n",
+        GenPassFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record([
+                "line",
+                "score",
+                "guesses_log10",
+                "online_throttled",
+                "online_unthrottled",
+                "offline_slow_hash",
+                "offline_fast_hash",
+                "warning",
+                "suggestions",
+            ])?;
+            for check in checks {
+                let times = &check.crack_times;
+                writer.write_record([
+                    check.line.to_string().as_str(),
+                    check.score.to_string().as_str(),
+                    format!("{:.2}", check.guesses_log10).as_str(),
+                    &times.online_throttled,
+                    &times.online_unthrottled,
+                    &times.offline_slow_hash,
+                    &times.offline_fast_hash,
+                    check.warning.as_deref().unwrap_or_default(),
+                    check.suggestions.join(" ").as_str(),
+                ])?;
+            }
+            String::from_utf8(writer.into_inner()?)?
+        }
+    };
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn check(content: &str, user_inputs: &[&str]) -> anyhow::Result<Vec<PasswordCheck>> {
+        let mut input = NamedTempFile::new()?;
+        input.write_all(content.as_bytes())?;
+        let user_inputs = user_inputs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        process_genpass_check(input.path(), &user_inputs)
+    }
+
+    #[test]
+    fn test_check_passwords() -> anyhow::Result<()> {
+        let checks = check("password\r\n\ncorrect horse battery staple\n", &[])?;
+        assert_eq!(checks.len(), 2);
+        assert_eq!((checks[0].line, checks[0].score), (1, 0));
+        assert!(checks[0].warning.is_some());
+        assert!(!checks[0].suggestions.is_empty());
+        assert_eq!(checks[1].line, 3);
+        assert!(checks[1].score >= 3);
+
+        let text = format_checks(&checks, GenPassFormat::Text)?;
+        assert!(text.starts_with("line 1: score 0/4\n  crack time: "));
+        let csv = format_checks(&checks, GenPassFormat::Csv)?;
+        assert_eq!(csv.lines().count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_user_inputs() -> anyhow::Result<()> {
+        let without = check("jsmith1987\n", &[])?;
+        let with = check("jsmith1987\n", &["jsmith"])?;
+        assert!(with[0].guesses_log10 < without[0].guesses_log10);
+        Ok(())
+    }
+}
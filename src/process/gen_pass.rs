@@ -1,52 +1,299 @@
 use rand::prelude::*;
 
-const UPPER: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &str = "abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &str = "123456789";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const NUMBER: &str = "0123456789";
 const SPECIAL: &str = "!@#$%^&*_";
+/// characters easily mistaken for one another, dropped by `no_ambiguous`
+const AMBIGUOUS: &str = "0O1Il";
+/// left out of the built-in classes unless `allow_ambiguous`, as rcli always has
+const LOOK_ALIKE: &str = "0OIl";
+/// longest password `process_genpass` builds, `--length` is checked against it
+pub const MAX_PASSWORD_LENGTH: usize = 4096;
+
+/// Which characters a password may use and how many of each class it needs
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub uppercase: bool,
+    pub lowercase: bool,
+    pub number: bool,
+    pub special: bool,
+    /// the only characters to use, replacing the classes above
+    pub charset: Option<String>,
+    /// symbols used for the special class instead of `!@#$%^&*_`
+    pub special_set: Option<String>,
+    /// never use these characters
+    pub exclude: String,
+    /// also drop look-alikes from `charset` and `special_set`
+    pub no_ambiguous: bool,
+    /// put `0 O I l` back into the built-in classes
+    pub allow_ambiguous: bool,
+    /// a minimum count also turns its class on
+    pub min_upper: usize,
+    pub min_digit: usize,
+    pub min_special: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            uppercase: true,
+            lowercase: true,
+            number: true,
+            special: false,
+            charset: None,
+            special_set: None,
+            exclude: String::new(),
+            no_ambiguous: false,
+            allow_ambiguous: false,
+            min_upper: 0,
+            min_digit: 0,
+            min_special: 0,
+        }
+    }
+}
+
+/// Characters of one class allowed by the policy and how many the password needs
+struct CharClass {
+    chars: Vec<char>,
+    min: usize,
+}
 
 // random generate password
 // we should decouple the logic from the cli parsing
 // consider opts length、uppercase、lowercase、numbers and symbols
-pub fn process_genpass(
-    length: u8,
-    uppercase: bool,
-    lowercase: bool,
-    number: bool,
-    special: bool,
-) -> anyhow::Result<String> {
-    let mut password = Vec::with_capacity(length as usize);
-    let mut charset = String::new();
+pub fn process_genpass(length: usize, policy: &PasswordPolicy) -> anyhow::Result<String> {
+    if length > MAX_PASSWORD_LENGTH {
+        anyhow::bail!("Password length {} is larger than {}", length, MAX_PASSWORD_LENGTH);
+    }
+    let (charset, classes) = policy.resolve()?;
+    let mut password = Vec::with_capacity(length);
     // let mut rng = rng();
     let mut rng = thread_rng();
-    if uppercase {
-        charset.push_str(UPPER);
-        password.push(UPPER.chars().nth(rng.gen_range(0..UPPER.len())).unwrap());
-    }
-    if lowercase {
-        charset.push_str(LOWER);
-        password.push(LOWER.chars().nth(rng.gen_range(0..LOWER.len())).unwrap());
-    }
-    if number {
-        charset.push_str(NUMBER);
-        password.push(NUMBER.chars().nth(rng.gen_range(0..NUMBER.len())).unwrap());
-    }
-    if special {
-        charset.push_str(SPECIAL);
-        password.push(SPECIAL.chars().nth(rng.gen_range(0..SPECIAL.len())).unwrap());
+    for class in &classes {
+        for _ in 0..class.min {
+            password.push(class.chars[rng.gen_range(0..class.chars.len())]);
+        }
     }
 
-    // when password length is less than the number of selected types, return error
-    if password.len() > length as usize {
-        anyhow::bail!("Password length is less than the number of selected character types");
+    // when password length is less than the number of required characters, return error
+    if password.len() > length {
+        anyhow::bail!(
+            "Password length {} is less than the {} required characters",
+            length,
+            password.len()
+        );
     }
 
-    for _ in password.len()..length as usize {
+    for _ in password.len()..length {
         let idx = rng.gen_range(0..charset.len());
-        password.push(charset.chars().nth(idx).unwrap());
+        password.push(charset[idx]);
     }
     password.shuffle(&mut rng);
     let password = String::from_iter(password);
 
     Ok(password)
 }
+
+impl PasswordPolicy {
+    /// Bits of randomness of a `length` character password, as if every character were drawn
+    /// from the whole charset. Required characters come from smaller sets, so this is an upper bound
+    pub fn entropy(&self, length: usize) -> anyhow::Result<f64> {
+        let (charset, _) = self.resolve()?;
+        Ok(length as f64 * (charset.len() as f64).log2())
+    }
+
+    /// The digits the policy allows
+    pub fn digits(&self) -> String {
+        self.allowed(NUMBER, true)
+    }
+
+    /// The symbols the policy allows, `special_set` or the built-in ones
+    pub fn symbols(&self) -> String {
+        match &self.special_set {
+            Some(chars) => self.allowed(chars, false),
+            None => self.allowed(SPECIAL, true),
+        }
+    }
+
+    /// `chars` without the excluded characters, `builtin` classes also without the look-alikes
+    fn allowed(&self, chars: &str, builtin: bool) -> String {
+        chars
+            .chars()
+            .filter(|c| {
+                !(self.exclude.contains(*c)
+                    || (self.no_ambiguous && AMBIGUOUS.contains(*c))
+                    || (builtin && !self.allow_ambiguous && LOOK_ALIKE.contains(*c)))
+            })
+            .collect()
+    }
+
+    /// The characters to draw from and the classes with a required count
+    fn resolve(&self) -> anyhow::Result<(Vec<char>, Vec<CharClass>)> {
+        let symbols = self.symbols();
+        let mut charset = Vec::new();
+        let mut add = |chars: &str| {
+            for c in chars.chars() {
+                if !charset.contains(&c) {
+                    charset.push(c);
+                }
+            }
+        };
+        // every enabled class needs at least one character, a custom charset only the minimums
+        let at_least = |enabled: bool, min: usize| if enabled { min.max(1) } else { min };
+        let mins = match &self.charset {
+            Some(chars) => {
+                add(&self.allowed(chars, false));
+                [self.min_upper, 0, self.min_digit, self.min_special]
+            }
+            None => {
+                let upper = at_least(self.uppercase, self.min_upper);
+                let lower = at_least(self.lowercase, 0);
+                let digit = at_least(self.number, self.min_digit);
+                let special = at_least(self.special, self.min_special);
+                for (min, chars) in [
+                    (upper, self.allowed(UPPER, true)),
+                    (lower, self.allowed(LOWER, true)),
+                    (digit, self.digits()),
+                    (special, symbols.clone()),
+                ] {
+                    if min > 0 {
+                        add(&chars);
+                    }
+                }
+                [upper, lower, digit, special]
+            }
+        };
+        if charset.is_empty() {
+            anyhow::bail!("No characters left to generate a password from");
+        }
+
+        let is_member = |class: usize, c: char| match class {
+            0 => c.is_uppercase(),
+            1 => c.is_lowercase(),
+            2 => c.is_ascii_digit(),
+            _ => symbols.contains(c) || !c.is_alphanumeric(),
+        };
+        let mut classes = Vec::new();
+        for (class, (name, min)) in
+            ["uppercase", "lowercase", "digit", "special"].into_iter().zip(mins).enumerate()
+        {
+            if min == 0 {
+                continue;
+            }
+            let chars =
+                charset.iter().copied().filter(|c| is_member(class, *c)).collect::<Vec<_>>();
+            if chars.is_empty() {
+                anyhow::bail!("No {} characters left to use", name);
+            }
+            classes.push(CharClass { chars, min });
+        }
+        Ok((charset, classes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(password: &str, chars: &str) -> usize {
+        password.chars().filter(|c| chars.contains(*c)).count()
+    }
+
+    #[test]
+    fn test_default_policy() -> anyhow::Result<()> {
+        let password = process_genpass(12, &PasswordPolicy::default())?;
+        assert_eq!(password.chars().count(), 12);
+        assert!(count(&password, UPPER) >= 1);
+        assert!(count(&password, LOWER) >= 1);
+        assert!(count(&password, NUMBER) >= 1);
+        assert_eq!(count(&password, SPECIAL), 0);
+        assert_eq!(count(&password, LOOK_ALIKE), 0);
+        // longer than the old u8 limit, e.g. for key material
+        assert_eq!(process_genpass(512, &PasswordPolicy::default())?.len(), 512);
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_policy() -> anyhow::Result<()> {
+        let policy = PasswordPolicy {
+            special_set: Some("-+=".into()),
+            exclude: "$".into(),
+            no_ambiguous: true,
+            min_upper: 3,
+            min_digit: 4,
+            min_special: 2,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(16, &policy)?;
+            assert!(count(&password, UPPER) >= 3);
+            assert!(count(&password, NUMBER) >= 4);
+            assert!(count(&password, "-+=") >= 2);
+            assert_eq!(count(&password, AMBIGUOUS), 0);
+            assert!(password.chars().all(|c| c.is_alphanumeric() || "-+=".contains(c)));
+        }
+
+        let policy = PasswordPolicy {
+            charset: Some("abc123!".into()),
+            exclude: "c".into(),
+            min_digit: 2,
+            ..Default::default()
+        };
+        let password = process_genpass(8, &policy)?;
+        assert!(password.chars().all(|c| "ab123!".contains(c)));
+        assert!(count(&password, NUMBER) >= 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguous() -> anyhow::Result<()> {
+        let policy = PasswordPolicy { allow_ambiguous: true, ..Default::default() };
+        let password = process_genpass(MAX_PASSWORD_LENGTH, &policy)?;
+        assert!(count(&password, LOOK_ALIKE) > 0);
+        // only the built-in classes leave them out by default
+        let policy = PasswordPolicy { charset: Some("0O".into()), ..Default::default() };
+        assert!(process_genpass(8, &policy)?.chars().all(|c| "0O".contains(c)));
+        let policy = PasswordPolicy { no_ambiguous: true, ..policy };
+        assert!(process_genpass(8, &policy).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_symbols() {
+        let policy = PasswordPolicy {
+            special_set: Some("-+=$".into()),
+            exclude: "$1".into(),
+            ..Default::default()
+        };
+        assert_eq!(policy.symbols(), "-+=");
+        assert_eq!(policy.digits(), "23456789");
+        let policy = PasswordPolicy { exclude: SPECIAL.into(), ..Default::default() };
+        assert_eq!(policy.symbols(), "");
+    }
+
+    #[test]
+    fn test_entropy() -> anyhow::Result<()> {
+        // 24 + 25 + 9 characters without the look-alikes
+        let entropy = PasswordPolicy::default().entropy(12)?;
+        assert!((entropy - 12.0 * 58f64.log2()).abs() < 1e-9);
+        let policy = PasswordPolicy { allow_ambiguous: true, ..Default::default() };
+        assert!((policy.entropy(12)? - 12.0 * 62f64.log2()).abs() < 1e-9);
+        let pin = PasswordPolicy { uppercase: false, lowercase: false, ..Default::default() };
+        assert!((pin.entropy(6)? - 6.0 * 9f64.log2()).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_impossible_policy() {
+        let policy = PasswordPolicy { min_upper: 10, ..Default::default() };
+        assert!(process_genpass(8, &policy).is_err());
+        let policy =
+            PasswordPolicy { charset: Some("abc".into()), min_digit: 1, ..Default::default() };
+        assert!(process_genpass(8, &policy).is_err());
+        let policy =
+            PasswordPolicy { charset: Some("$".into()), exclude: "$".into(), ..Default::default() };
+        assert!(process_genpass(8, &policy).is_err());
+        assert!(process_genpass(usize::MAX, &Default::default()).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use rand::prelude::*;
+
+/// The EFF large wordlist: 7776 words, one per roll of five dice
+const EFF_LARGE_WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+/// most words `process_genphrase` picks, `--words` is checked against it
+pub const MAX_PASSPHRASE_WORDS: usize = 256;
+
+/// Generate a diceware passphrase of `words` words from the EFF large wordlist or `wordlist`.
+/// `digits` and `symbols`, when given, append one of their characters to a random word each.
+/// Returns the passphrase and its entropy in bits, capitalization adds none
+pub fn process_genphrase(
+    words: usize,
+    separator: &str,
+    wordlist: Option<&Path>,
+    capitalize: bool,
+    digits: Option<&str>,
+    symbols: Option<&str>,
+) -> anyhow::Result<(String, f64)> {
+    if words == 0 {
+        anyhow::bail!("A passphrase needs at least one word");
+    }
+    if words > MAX_PASSPHRASE_WORDS {
+        anyhow::bail!("A passphrase has at most {} words, got {}", MAX_PASSPHRASE_WORDS, words);
+    }
+    let content = match wordlist {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => EFF_LARGE_WORDLIST.to_string(),
+    };
+    let list = parse_wordlist(&content);
+    if list.len() < 2 {
+        anyhow::bail!("The wordlist needs at least two distinct words, found {}", list.len());
+    }
+
+    let mut rng = thread_rng();
+    let mut phrase = (0..words)
+        .map(|_| {
+            let word = list[rng.gen_range(0..list.len())];
+            if capitalize { capitalized(word) } else { word.to_string() }
+        })
+        .collect::<Vec<_>>();
+    let mut entropy = words as f64 * (list.len() as f64).log2();
+    for (name, chars) in [("digits", digits), ("symbols", symbols)] {
+        let Some(chars) = chars else { continue };
+        let chars = chars.chars().collect::<Vec<_>>();
+        if chars.is_empty() {
+            anyhow::bail!("No {} left to inject", name);
+        }
+        let idx = rng.gen_range(0..phrase.len());
+        phrase[idx].push(chars[rng.gen_range(0..chars.len())]);
+        entropy += (chars.len() as f64).log2() + (words as f64).log2();
+    }
+    Ok((phrase.join(separator), entropy))
+}
+
+/// One word per line, either plain or in diceware's `11111<TAB>word` form.
+/// Blank lines and `#` comments are skipped, repeated words count once
+fn parse_wordlist(content: &str) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once(char::is_whitespace) {
+            Some((roll, word)) if roll.chars().all(|c| c.is_ascii_digit()) => word.trim(),
+            _ => line,
+        })
+        .filter(|word| seen.insert(*word))
+        .collect()
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_eff_wordlist() {
+        let list = parse_wordlist(EFF_LARGE_WORDLIST);
+        assert_eq!(list.len(), 7776);
+        assert_eq!(list[0], "abacus");
+        assert_eq!(list[7775], "zoom");
+    }
+
+    #[test]
+    fn test_genphrase() -> anyhow::Result<()> {
+        let (phrase, entropy) = process_genphrase(6, " ", None, false, None, None)?;
+        assert_eq!(phrase.split(' ').count(), 6);
+        // log2(7776) is about 12.9 bits per word
+        assert!((entropy - 77.55).abs() < 0.01);
+
+        let (phrase, entropy) =
+            process_genphrase(4, ".", None, true, Some("0123456789"), Some("-+"))?;
+        let words = phrase.split('.').collect::<Vec<_>>();
+        assert_eq!(words.len(), 4);
+        assert!(words.iter().all(|w| w.starts_with(|c: char| c.is_uppercase())));
+        assert_eq!(phrase.chars().filter(char::is_ascii_digit).count(), 1);
+        assert_eq!(phrase.chars().filter(|c| "-+".contains(*c)).count(), 1);
+        assert!((entropy - (4.0 * 7776f64.log2() + 10f64.log2() + 1.0 + 4.0)).abs() < 1e-9);
+        assert!(process_genphrase(4, ".", None, false, None, Some("")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_wordlist() -> anyhow::Result<()> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(b"# team list\nalpha\n\nbravo\n11 charlie\nalpha\n")?;
+        let (phrase, entropy) = process_genphrase(3, "-", Some(file.path()), false, None, None)?;
+        assert!(phrase.split('-').all(|w| ["alpha", "bravo", "charlie"].contains(&w)));
+        assert!((entropy - 3.0 * 3f64.log2()).abs() < 1e-9);
+
+        let mut file = NamedTempFile::new()?;
+        file.write_all(b"only\nonly\n")?;
+        assert!(process_genphrase(3, "-", Some(file.path()), false, None, None).is_err());
+        assert!(process_genphrase(usize::MAX, "-", None, false, None, None).is_err());
+        Ok(())
+    }
+}
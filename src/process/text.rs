@@ -8,7 +8,7 @@ use base64::prelude::*;
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
 
-use crate::{TextSignFormat, get_reader, process_genpass};
+use crate::{PasswordPolicy, TextSignFormat, get_reader, process_genpass};
 
 pub trait TextSign {
     /// Dynamic dispatch on reader to support stdin or file input
@@ -151,7 +151,8 @@ impl TextVerify for Ed25519Verifier {
 
 impl KeyGenerator for Blake3 {
     fn generate() -> Result<Vec<Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let policy = PasswordPolicy { special: true, ..Default::default() };
+        let key = process_genpass(32, &policy)?;
         let key = key.into_bytes();
         Ok(vec![key])
     }
@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
 
 pub fn get_reader(input: &Path) -> anyhow::Result<Box<dyn Read>> {
@@ -10,3 +10,12 @@ pub fn get_reader(input: &Path) -> anyhow::Result<Box<dyn Read>> {
     };
     Ok(reader)
 }
+
+pub fn get_writer(output: &Path) -> anyhow::Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output.to_str() == Some("-") {
+        Box::new(BufWriter::new(std::io::stdout()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    Ok(writer)
+}